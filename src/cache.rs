@@ -0,0 +1,207 @@
+//! ファイルの内容のハッシュによるテスト結果のキャッシュです。
+//!
+//! ライブラリ、それがインクルードしているファイル、テストプロジェクトの内容が前回成功した
+//! ときから変わっていなければ、そのテストは実行せずに済ませます。
+
+use crate::deps;
+use crate::runner;
+use crate::{Result, Test, TestResult};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// ライブラリのルートに作るキャッシュファイルの名前
+const CACHE_FILE: &str = ".procon-lib-tester-cache";
+
+/// キャッシュファイルの形式が変わったら上げる番号
+const CACHE_VERSION: u32 = 1;
+
+/// キャッシュされたテスト一つの結果です。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    /// テストを実行する直前の内容のハッシュ
+    hash: String,
+
+    result: TestResult,
+}
+
+/// キャッシュファイルの内容です。
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+
+    /// テストの名前 → 結果
+    entries: BTreeMap<String, Entry>,
+}
+
+/// テスト結果のキャッシュです。並列に実行されるテストから同時に使えます。
+#[derive(Debug)]
+pub struct ResultCache {
+    path: PathBuf,
+    library_root: PathBuf,
+
+    /// ランナーの設定など、テストの結果に影響するファイル以外の情報。ハッシュに含めます。
+    salt: String,
+
+    /// キャッシュを参照するかどうか (`false` でも結果は記録します)
+    lookup: bool,
+
+    entries: Mutex<BTreeMap<String, Entry>>,
+}
+
+impl ResultCache {
+    /// ライブラリのルートからキャッシュを読み込みます。キャッシュファイルが無いか、形式が
+    /// 古い場合は空のキャッシュを返します。
+    pub fn load(library_root: &Path, salt: String, lookup: bool) -> Result<ResultCache> {
+        let path = file_path(library_root);
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<CacheFile>(&content)
+                .ok()
+                .filter(|file| file.version == CACHE_VERSION)
+                .map(|file| file.entries)
+                .unwrap_or_default(),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("failed to read {}: {}", path.display(), e).into()),
+        };
+
+        Ok(ResultCache {
+            path,
+            library_root: library_root.to_path_buf(),
+            salt,
+            lookup,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// 内容のハッシュが `hash` のテストが、前回成功したときから変わっていなければ `true` を
+    /// 返します。
+    pub fn is_fresh(&self, test: &Test, hash: &str) -> bool {
+        if !self.lookup {
+            return false;
+        }
+
+        match self.entries.lock().unwrap().get(&self.key(test)) {
+            Some(entry) => entry.result == TestResult::Succeeded && entry.hash == hash,
+            None => false,
+        }
+    }
+
+    /// `test` の結果を記録します。`hash` はテストを実行する前に `hash` で計算した内容の
+    /// ハッシュです。実行中に変更されたファイルの内容で成功を記録しないよう、実行後に計算し
+    /// 直してはいけません。
+    pub fn record(&self, test: &Test, hash: String, result: TestResult) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(self.key(test), Entry { hash, result });
+    }
+
+    /// テストプロジェクトが存在しない `test` の結果を忘れます。
+    pub fn forget(&self, test: &Test) {
+        self.entries.lock().unwrap().remove(&self.key(test));
+    }
+
+    /// キャッシュをファイルに書き出します。
+    pub fn save(&self) -> Result<()> {
+        let file = CacheFile {
+            version: CACHE_VERSION,
+            entries: self.entries.lock().unwrap().clone(),
+        };
+
+        fs::write(&self.path, serde_json::to_string_pretty(&file)?)
+            .map_err(|e| format!("failed to write {}: {}", self.path.display(), e).into())
+    }
+
+    fn key(&self, test: &Test) -> String {
+        test.name(&self.library_root)
+    }
+
+    /// ライブラリとそれが (間接的に) インクルードしているファイル、およびテストプロジェクト内の
+    /// 全てのファイルの内容のハッシュを計算します。
+    pub fn hash(&self, test: &Test) -> io::Result<String> {
+        let mut hasher = Fnv1a::new();
+        hasher.write(self.salt.as_bytes());
+
+        // インクルードされているファイルは、存在しないものも含めて名前順に並べる
+        for source in &deps::with_includes(Some(&*test.library))? {
+            hasher.write_file(source, &self.library_root)?;
+        }
+
+        let mut files = Vec::new();
+        collect_files(&test.project, &mut files)?;
+        files.sort();
+        for file in &files {
+            hasher.write_file(file, &test.project)?;
+        }
+
+        Ok(format!("{:016x}", hasher.finish()))
+    }
+}
+
+/// `dir` 以下のファイルを全て `files` に追加します。
+///
+/// ネイティブランナーの作った実行ファイルなどは、他の組み合わせの実行で作られることもあるので
+/// 含めません。
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if !runner::is_build_output(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// ライブラリのルート `library_root` に置くキャッシュファイルのパスを返します。
+pub fn file_path(library_root: &Path) -> PathBuf {
+    library_root.join(CACHE_FILE)
+}
+
+/// 実行ごとに値の変わらないハッシュ関数 (FNV-1a 64bit) です。
+///
+/// 標準ライブラリの `DefaultHasher` はバージョンによって値が変わりうるので、キャッシュには
+/// 使いません。
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// ファイルの `base` からの相対パスと内容を書き込みます。存在しないファイルは名前だけ
+    /// 書き込みます。
+    fn write_file(&mut self, path: &Path, base: &Path) -> io::Result<()> {
+        let name = path.strip_prefix(base).unwrap_or(path);
+        self.write(name.to_string_lossy().as_bytes());
+        self.write(&[0]);
+
+        match fs::read(path) {
+            Ok(content) => {
+                self.write(&(content.len() as u64).to_le_bytes());
+                self.write(&content);
+                Ok(())
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,376 @@
+//! コマンドライン引数を定義します。
+//!
+//! ここでのドキュメントコメントはそのまま `--help` の説明として表示されるので、英語で書きます。
+
+use crate::runner::{RunnerKind, Sanitizer};
+use crate::{parse_secs, OutputFormat};
+
+use clap::builder::PossibleValue;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// サブコマンドの前にも書ける、全体に効くオプションの ID
+const GLOBAL_ARGS: &[&str] = &["color", "test_extension"];
+
+/// Tester for Programming Competition Library
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// When to colorize the output
+    #[arg(
+        long,
+        global = true,
+        value_name = "WHEN",
+        value_parser = ["always", "none", "auto"]
+    )]
+    pub color: Option<String>,
+
+    /// Use `<library>.<EXT>` as the test project of each library
+    #[arg(long, global = true, value_name = "EXT")]
+    pub test_extension: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // サブコマンドなしで指定された `run` の引数
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run tests (default)
+    Run(Box<RunArgs>),
+
+    /// List discovered tests without running them
+    List(ListArgs),
+
+    /// Print the include graph of libraries in DOT format
+    IncludeGraph,
+
+    /// Create missing test projects from the template
+    InitTests(FilterArgs),
+
+    /// Report libraries without test projects
+    Coverage(CoverageArgs),
+
+    /// Check that each header compiles on its own
+    CheckHeaders(CheckHeadersArgs),
+
+    /// Remove the result cache and executables built by the native runner
+    Clean,
+}
+
+/// テストするライブラリを絞り込む引数です。
+#[derive(Debug, Args)]
+pub struct FilterArgs {
+    /// Only use libraries matching one of these patterns (substring or glob)
+    #[arg(value_name = "PATTERN")]
+    pub patterns: Vec<String>,
+
+    /// Skip libraries matching this pattern
+    #[arg(short = 'x', long = "exclude", value_name = "PATTERN")]
+    pub excludes: Vec<String>,
+
+    /// Interpret patterns as regular expressions
+    #[arg(long)]
+    pub regex: bool,
+}
+
+/// `run` サブコマンドの引数です。`Option` のものは省略すると設定ファイルの値を使います。
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Pass `--force` to procon-assistant (default)
+    #[arg(long, overrides_with = "no_force")]
+    force: bool,
+
+    /// Do not pass `--force` to procon-assistant
+    #[arg(short = 'n', long, overrides_with = "force")]
+    no_force: bool,
+
+    /// Hide the output of tests
+    #[arg(short = 's', long, overrides_with = "no_simple")]
+    simple: bool,
+
+    /// Show the output of tests (default)
+    #[arg(long, overrides_with = "simple")]
+    no_simple: bool,
+
+    /// Run N tests in parallel (0 for the number of CPUs)
+    #[arg(short = 'j', long, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Time limit of each test in seconds
+    #[arg(short = 't', long, value_name = "SECS", value_parser = parse_timeout)]
+    pub timeout: Option<Duration>,
+
+    /// Re-run failed tests up to N times
+    #[arg(long, value_name = "N")]
+    pub retries: Option<usize>,
+
+    /// Write a JUnit XML report to PATH
+    #[arg(long, value_name = "PATH")]
+    pub junit: Option<String>,
+
+    /// Write the output of each test to DIR/<library>.log
+    #[arg(long, value_name = "DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    /// Print the N slowest tests at the end
+    #[arg(long, value_name = "N")]
+    pub slowest: Option<usize>,
+
+    /// Output format
+    #[arg(short = 'f', long)]
+    pub format: Option<OutputFormat>,
+
+    /// Only run tests affected by uncommitted changes
+    #[arg(long)]
+    changed: bool,
+
+    /// Only run tests affected by changes since REV (implies --changed)
+    #[arg(long, value_name = "REV")]
+    since: Option<String>,
+
+    /// Run tests of FILE and of libraries including it
+    #[arg(long, value_name = "FILE")]
+    pub with_dependents: Vec<String>,
+
+    /// Run tests of libraries included by FILE
+    #[arg(long, value_name = "FILE")]
+    pub deps_of: Vec<String>,
+
+    /// Keep watching files and re-run affected tests
+    #[arg(short = 'w', long)]
+    pub watch: bool,
+
+    /// Skip tests unchanged since they last succeeded (default)
+    #[arg(long, overrides_with = "no_cache")]
+    cache: bool,
+
+    /// Run all tests regardless of the cache
+    #[arg(long, overrides_with = "cache")]
+    no_cache: bool,
+
+    #[command(flatten)]
+    pub require_tests: RequireTestsArgs,
+
+    /// How to run test projects
+    #[arg(long)]
+    pub runner: Option<RunnerKind>,
+
+    /// Compiler used by the native runner
+    #[arg(long, value_name = "COMMAND")]
+    pub compiler: Option<String>,
+
+    /// Compiler flags used by the native runner
+    #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+    pub compiler_flags: Option<String>,
+
+    /// Command run by the custom runner
+    #[arg(long, value_name = "COMMAND", allow_hyphen_values = true)]
+    pub runner_command: Option<String>,
+
+    /// Build and run tests with these sanitizers (native runner only)
+    #[arg(long, value_name = "LIST", value_delimiter = ',')]
+    pub sanitize: Option<Vec<Sanitizer>>,
+}
+
+/// `list` サブコマンドの引数です。
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Output format
+    #[arg(short = 'f', long)]
+    pub format: Option<OutputFormat>,
+}
+
+/// `coverage` サブコマンドの引数です。
+#[derive(Debug, Args)]
+pub struct CoverageArgs {
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    #[command(flatten)]
+    pub require_tests: RequireTestsArgs,
+}
+
+/// `check-headers` サブコマンドの引数です。
+#[derive(Debug, Args)]
+pub struct CheckHeadersArgs {
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Hide the compiler output
+    #[arg(short = 's', long)]
+    pub simple: bool,
+
+    /// Check N headers in parallel (0 for the number of CPUs)
+    #[arg(short = 'j', long, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Compiler used for the check
+    #[arg(long, value_name = "COMMAND")]
+    pub compiler: Option<String>,
+
+    /// Compiler flags used for the check
+    #[arg(long, value_name = "FLAGS", allow_hyphen_values = true)]
+    pub compiler_flags: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RequireTestsArgs {
+    /// Fail if some libraries have no test projects
+    #[arg(long, overrides_with = "no_require_tests")]
+    require_tests: bool,
+
+    /// Do not fail on libraries without test projects (default)
+    #[arg(long, overrides_with = "require_tests")]
+    no_require_tests: bool,
+}
+
+impl Cli {
+    /// コマンドライン引数を解釈します。
+    ///
+    /// サブコマンドの前に書けるのは全体に効くオプションだけです。`run` の引数の後にサブコマンドを
+    /// 書いた場合は、どちらかが黙って無視されることのないようエラーとします。
+    pub fn parse_args() -> Cli {
+        let mut command = Cli::command();
+        let matches = command.get_matches_mut();
+        if let Some((name, _)) = matches.subcommand() {
+            let misplaced = command.get_arguments().find(|arg| {
+                let id = arg.get_id().as_str();
+                !GLOBAL_ARGS.contains(&id)
+                    && matches.value_source(id) == Some(ValueSource::CommandLine)
+            });
+            if let Some(arg) = misplaced {
+                let arg = match arg.get_long() {
+                    Some(long) => format!("--{}", long),
+                    None => format!("<{}>", arg.get_id()),
+                };
+                let msg = format!("`{}` cannot be used before the subcommand `{}`", arg, name);
+                command.error(ErrorKind::ArgumentConflict, msg).exit();
+            }
+        }
+
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+}
+
+impl RunArgs {
+    pub fn force(&self) -> Option<bool> {
+        flag(self.force, self.no_force)
+    }
+
+    pub fn simple(&self) -> Option<bool> {
+        flag(self.simple, self.no_simple)
+    }
+
+    pub fn cache(&self) -> Option<bool> {
+        flag(self.cache, self.no_cache)
+    }
+
+    /// 変更で絞り込むときに比べるリビジョンを返します。絞り込まなければ `None` を返します。
+    pub fn changed_since(&self) -> Option<&str> {
+        match &self.since {
+            Some(rev) => Some(rev),
+            None if self.changed => Some("HEAD"),
+            None => None,
+        }
+    }
+}
+
+impl RequireTestsArgs {
+    pub fn get(&self) -> Option<bool> {
+        flag(self.require_tests, self.no_require_tests)
+    }
+}
+
+impl ValueEnum for OutputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            OutputFormat::Text,
+            OutputFormat::Json,
+            OutputFormat::JsonLines,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        let value = match self {
+            OutputFormat::Text => PossibleValue::new("text").help("Colored text for humans"),
+            OutputFormat::Json => PossibleValue::new("json").help("One JSON document"),
+            OutputFormat::JsonLines => {
+                PossibleValue::new("jsonl").help("One JSON object per line as tests finish")
+            }
+        };
+
+        Some(value)
+    }
+}
+
+impl ValueEnum for RunnerKind {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            RunnerKind::ProconAssistant,
+            RunnerKind::Native,
+            RunnerKind::Custom,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        let value = match self {
+            RunnerKind::ProconAssistant => PossibleValue::new("procon-assistant")
+                .help("Run `procon-assistant run` in the project"),
+            RunnerKind::Native => {
+                PossibleValue::new("native").help("Compile main.cpp and compare sample outputs")
+            }
+            RunnerKind::Custom => {
+                PossibleValue::new("custom").help("Run the command given by --runner-command")
+            }
+        };
+
+        Some(value)
+    }
+}
+
+impl ValueEnum for Sanitizer {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Sanitizer::Address, Sanitizer::Undefined]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        let value = match self {
+            Sanitizer::Address => {
+                PossibleValue::new("address").help("Out-of-bounds accesses and memory leaks")
+            }
+            Sanitizer::Undefined => {
+                PossibleValue::new("undefined").help("Undefined behavior such as overflows")
+            }
+        };
+
+        Some(value)
+    }
+}
+
+/// `--foo` と `--no-foo` の組を、どちらも指定されなければ `None` となる値にします。
+fn flag(yes: bool, no: bool) -> Option<bool> {
+    match (yes, no) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// `--timeout` に与えられた秒数を解釈します。
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    parse_secs(value).ok_or_else(|| format!("invalid timeout: {}", value))
+}
@@ -0,0 +1,171 @@
+//! ライブラリのルートに置く設定ファイルを扱います。
+//!
+//! 設定は `procon-lib-tester.toml` か、ルートの目印である `marker_lib_root` に TOML 形式で
+//! 書きます。どちらの設定もコマンドライン引数で上書きできます。
+
+use crate::layout::LayoutKind;
+use crate::runner::{RunnerKind, Sanitizer, Settings};
+use crate::{OutputFormat, Result};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// 設定ファイルの名前
+pub const CONFIG_FILE: &str = "procon-lib-tester.toml";
+
+/// ライブラリのルートの目印となるファイルの名前 (設定を書くこともできます)
+pub const MARKER_FILE: &str = "marker_lib_root";
+
+/// 設定ファイルの内容を表す構造体です。書かれていない項目は `None` や空になります。
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// 出力を色付けするかどうか (`always`, `none`, `auto`)
+    pub color: Option<String>,
+
+    /// `--no-force` を指定しなかったときに procon-assistant に `--force` を渡すかどうか
+    pub force: Option<bool>,
+
+    /// `--simple` を指定しなかったときにテストの出力を表示しないかどうか
+    pub simple: Option<bool>,
+
+    /// 並列に実行するテストの数 (0 は利用可能な CPU 数)
+    pub jobs: Option<usize>,
+
+    /// テスト一つあたりの制限時間 (秒)
+    pub timeout: Option<f64>,
+
+    /// 失敗したテストを実行し直す最大の回数
+    pub retries: Option<usize>,
+
+    pub format: Option<OutputFormat>,
+
+    /// 前回成功したときから変わっていないテストを実行しないかどうか
+    pub cache: Option<bool>,
+
+    /// テストプロジェクトのないライブラリがあれば失敗とするかどうか
+    pub require_tests: Option<bool>,
+
+    /// ネイティブランナーでビルドに組み込むサニタイザ (`["address", "undefined"]` など)
+    pub sanitize: Option<Vec<Sanitizer>>,
+
+    /// 実行しないテストのパターン (コマンドラインの `--exclude` と合わせて使います)
+    pub exclude: Vec<String>,
+
+    /// ライブラリとして扱うファイルの拡張子。省略すると `hpp` だけとします。
+    pub library_extensions: Option<Vec<String>>,
+
+    /// ライブラリ `foo.hpp` のテストプロジェクトを `foo.<test-extension>` とします。
+    pub test_extension: Option<String>,
+
+    pub projects: ProjectsConfig,
+
+    /// `init-tests` で使うテストプロジェクトの雛形のディレクトリ (ライブラリのルートからのパス)
+    pub test_template: Option<String>,
+
+    pub runner: RunnerConfig,
+
+    /// ライブラリの拡張子ごとのランナーの設定 (`[runners.rs]` など)。ここにない拡張子の
+    /// ライブラリには `[runner]` の設定を使います。
+    pub runners: BTreeMap<String, RunnerConfig>,
+
+    /// テストを実行するコンパイラや言語標準の組み合わせ (`[[matrix]]`)。空でなければ、各テストを
+    /// 組み合わせごとに実行します。
+    pub matrix: Vec<MatrixConfig>,
+}
+
+/// 設定ファイルの `[projects]` セクションを表す構造体です。
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProjectsConfig {
+    /// テストプロジェクトを置く場所の規則。省略すると `sibling` とします。
+    pub layout: Option<LayoutKind>,
+
+    /// `mirror` の規則でテストプロジェクトを置くディレクトリ (ライブラリのルートからのパス)
+    pub dir: Option<String>,
+
+    /// ライブラリ → テストプロジェクトの対応表 (どちらもライブラリのルートからのパス)。
+    /// ここに書かれたライブラリは規則によらずこの表に従います。
+    pub map: BTreeMap<String, String>,
+}
+
+/// 設定ファイルの `[runner]` セクションを表す構造体です。
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RunnerConfig {
+    /// 使うランナー。省略すると `command` があればそれを、なければ procon-assistant を使います。
+    pub kind: Option<RunnerKind>,
+
+    /// `custom` ランナーで実行するコマンド
+    pub command: Option<String>,
+
+    /// `native` ランナーで使うコンパイラ
+    pub compiler: Option<String>,
+
+    /// `native` ランナーで使うコンパイルオプション (空白区切り)
+    pub compiler_flags: Option<String>,
+}
+
+/// 設定ファイルの `[[matrix]]` の要素一つ (組み合わせ一つ) を表す構造体です。省略した項目は
+/// `[runner]` などの設定のままとします。
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MatrixConfig {
+    /// 結果の表などに表示する組み合わせの名前
+    pub name: String,
+
+    /// 使うコンパイラ
+    pub compiler: Option<String>,
+
+    /// 使う言語標準 (`c++17` など)
+    pub std: Option<String>,
+
+    /// 使うコンパイルオプション (空白区切り)
+    pub compiler_flags: Option<String>,
+}
+
+impl From<RunnerConfig> for Settings {
+    fn from(config: RunnerConfig) -> Settings {
+        Settings {
+            kind: config.kind,
+            command: config.command,
+            compiler: config.compiler,
+            compiler_flags: config.compiler_flags,
+            standard: None,
+            combination: None,
+            sanitizers: Vec::new(),
+        }
+    }
+}
+
+/// ライブラリのルートから設定を読み込みます。
+///
+/// `procon-lib-tester.toml` があればそれを、なければ `marker_lib_root` の内容を設定として
+/// 読みます。両方に設定が書かれている場合はどちらを使うべきか分からないのでエラーとします。
+pub fn load(library_root: &Path) -> Result<Config> {
+    let marker = library_root.join(MARKER_FILE);
+    let marker_content = fs::read_to_string(&marker)?;
+
+    let config_file = library_root.join(CONFIG_FILE);
+    let (path, content) = if config_file.exists() {
+        if !marker_content.trim().is_empty() {
+            return Err(format!(
+                "configuration found in both {} and {}",
+                marker.display(),
+                config_file.display()
+            )
+            .into());
+        }
+
+        let content = fs::read_to_string(&config_file)?;
+        (config_file, content)
+    } else {
+        (marker, marker_content)
+    };
+
+    toml::from_str(&content)
+        .map_err(|e| format!("invalid config in {}: {}", path.display(), e).into())
+}
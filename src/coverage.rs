@@ -0,0 +1,100 @@
+//! テストプロジェクトのあるライブラリの割合を集計します。
+
+use crate::{path_root_removed, Test, TestResult};
+
+use colored_print::color::ConsoleColor as CC;
+use colored_print::colored_println;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// ディレクトリ一つの集計結果です。
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// テストプロジェクトのあるライブラリの数
+    pub tested: usize,
+
+    /// テストプロジェクトのないライブラリ (ライブラリのルートからの相対パス)
+    pub missing: Vec<String>,
+}
+
+impl Coverage {
+    fn total(&self) -> usize {
+        self.tested + self.missing.len()
+    }
+
+    fn percentage(&self) -> f64 {
+        if self.total() == 0 {
+            100.0
+        } else {
+            self.tested as f64 * 100.0 / self.total() as f64
+        }
+    }
+
+    fn add(&mut self, other: &Coverage) {
+        self.tested += other.tested;
+        self.missing.extend(other.missing.iter().cloned());
+    }
+}
+
+/// `tests` をライブラリのあるディレクトリ (ルートからの相対パス、ルート直下は `.`) ごとに
+/// 集計します。
+pub fn collect(tests: &[Test], library_root: &Path) -> BTreeMap<String, Coverage> {
+    let mut dirs = BTreeMap::<String, Coverage>::new();
+    let mut seen = BTreeSet::new();
+    for test in tests {
+        // テストプロジェクトが複数あるライブラリも一つとして数える
+        if !seen.insert(&test.library) {
+            continue;
+        }
+
+        let name = path_root_removed(&test.library, library_root).replace('\\', "/");
+        let dir = match name.rfind('/') {
+            Some(pos) => name[..pos].to_string(),
+            None => ".".to_string(),
+        };
+
+        let coverage = dirs.entry(dir).or_default();
+        if test.project.exists() {
+            coverage.tested += 1;
+        } else {
+            coverage.missing.push(name);
+        }
+    }
+
+    dirs
+}
+
+/// ディレクトリごとの集計結果と全体の集計結果を表示し、全体の集計結果を返します。
+pub fn print(dirs: &BTreeMap<String, Coverage>, colorize: bool) -> Coverage {
+    let mut overall = Coverage::default();
+    for (dir, coverage) in dirs {
+        print_line(dir, coverage, colorize);
+        for name in &coverage.missing {
+            colored_println! {
+                colorize;
+                CC::Reset, "    ";
+                TestResult::NotFound.get_color(), "{}", name;
+            }
+        }
+        overall.add(coverage);
+    }
+
+    print_line("total", &overall, colorize);
+
+    overall
+}
+
+fn print_line(label: &str, coverage: &Coverage, colorize: bool) {
+    let color = if coverage.missing.is_empty() {
+        TestResult::Succeeded.get_color()
+    } else {
+        TestResult::NotFound.get_color()
+    };
+
+    colored_println! {
+        colorize;
+        CC::Reset, "{}: ", label;
+        color, "{}/{} ({:.1}%)", coverage.tested, coverage.total(), coverage.percentage();
+    }
+}
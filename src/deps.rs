@@ -0,0 +1,190 @@
+//! ライブラリ同士の `#include "..."` による依存関係を扱います。
+
+use crate::path_root_removed;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// ライブラリのインクルード関係を表すグラフです。
+#[derive(Debug, Default)]
+pub struct IncludeGraph {
+    /// ライブラリ → そのライブラリが直接インクルードしているファイル
+    includes: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+}
+
+impl IncludeGraph {
+    /// `libraries` のソースを読み、インクルード関係のグラフを作ります。
+    pub fn build<'a, I>(libraries: I) -> io::Result<IncludeGraph>
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        let mut includes = BTreeMap::new();
+        for library in libraries {
+            includes.insert(normalize(library), includes_of(library)?);
+        }
+
+        Ok(IncludeGraph { includes })
+    }
+
+    /// `library` がグラフに含まれるライブラリかどうかを返します。
+    pub fn contains(&self, library: &Path) -> bool {
+        self.includes.contains_key(&normalize(library))
+    }
+
+    /// `targets` のいずれかを直接的または間接的にインクルードしているライブラリを返します。
+    /// `targets` 自身は (循環していない限り) 含みません。
+    pub fn dependents(&self, targets: &BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+        let mut dependents = BTreeSet::new();
+        let mut stack: Vec<PathBuf> = targets.iter().map(|target| normalize(target)).collect();
+        while let Some(target) = stack.pop() {
+            for (library, includes) in &self.includes {
+                if includes.contains(&target) && dependents.insert(library.clone()) {
+                    stack.push(library.clone());
+                }
+            }
+        }
+
+        dependents
+    }
+
+    /// `targets` のいずれかが直接的または間接的にインクルードしているファイルを返します。
+    /// `targets` 自身は (循環していない限り) 含みません。
+    pub fn dependencies(&self, targets: &BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+        let mut dependencies = BTreeSet::new();
+        let mut stack: Vec<PathBuf> = targets.iter().map(|target| normalize(target)).collect();
+        while let Some(target) = stack.pop() {
+            let includes = match self.includes.get(&target) {
+                Some(includes) => includes,
+                None => continue,
+            };
+
+            for include in includes {
+                if dependencies.insert(include.clone()) {
+                    stack.push(include.clone());
+                }
+            }
+        }
+
+        dependencies
+    }
+
+    /// グラフを DOT 形式で出力します。ファイル名は `root` からの相対パスで表します。
+    pub fn to_dot(&self, root: &Path) -> String {
+        let name = |path: &Path| {
+            path_root_removed(path, root)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        };
+
+        let mut dot = String::from("digraph includes {\n");
+        for (library, includes) in &self.includes {
+            if includes.is_empty() {
+                let _ = writeln!(dot, "    \"{}\";", name(library));
+            }
+
+            for include in includes {
+                let _ = writeln!(dot, "    \"{}\" -> \"{}\";", name(library), name(include));
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+/// `file` が直接インクルードしているファイルのパスを返します。
+pub fn includes_of(file: &Path) -> io::Result<BTreeSet<PathBuf>> {
+    let source = fs::read_to_string(file)?;
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    let includes = parse_includes(&source)
+        .map(|target| normalize(&dir.join(target)))
+        .collect();
+
+    Ok(includes)
+}
+
+/// `files` と、それらが直接的または間接的にインクルードしているファイルを返します。
+///
+/// インクルードされているファイルは、存在しないものも含めます。
+pub fn with_includes<'a, I>(files: I) -> io::Result<BTreeSet<PathBuf>>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let mut found = BTreeSet::new();
+    let mut stack: Vec<_> = files.into_iter().map(normalize).collect();
+    while let Some(file) = stack.pop() {
+        if !found.insert(file.clone()) || !file.is_file() {
+            continue;
+        }
+        stack.extend(includes_of(&file)?);
+    }
+
+    Ok(found)
+}
+
+/// ソースコード中の `#include "..."` で指定されたパスを列挙します。
+///
+/// `#include <...>` は標準ライブラリなどライブラリの外を指すので無視します。
+fn parse_includes(source: &str) -> impl Iterator<Item = &str> {
+    source.lines().filter_map(|line| {
+        let rest = line.trim_start().strip_prefix('#')?;
+        let rest = rest.trim_start().strip_prefix("include")?;
+        let rest = rest.trim_start().strip_prefix('"')?;
+        rest.find('"').map(|end| &rest[..end])
+    })
+}
+
+/// `.` や `..` を取り除いたパスを返します。
+///
+/// 削除されたファイルも扱えるよう、ファイルシステムには問い合わせず字面だけで正規化します。
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // 先頭の `..` は打ち消せないので残し、ルートの親はルートとする
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn includes(source: &str) -> Vec<&str> {
+        parse_includes(source).collect()
+    }
+
+    #[test]
+    fn parse_includes_finds_quoted_includes() {
+        let source = "#include \"a.hpp\"\n  #  include   \"../b/c.hpp\" // comment\n";
+        assert_eq!(includes(source), ["a.hpp", "../b/c.hpp"]);
+    }
+
+    #[test]
+    fn parse_includes_ignores_system_headers_and_other_lines() {
+        let source =
+            "#include <vector>\n#pragma once\n#define INC \"x.hpp\"\n#include \"unterminated\n";
+        assert!(includes(source).is_empty());
+    }
+
+    #[test]
+    fn normalize_removes_dots() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize(Path::new("../a/../../b")), Path::new("../../b"));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    }
+}
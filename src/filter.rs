@@ -0,0 +1,186 @@
+//! 実行するテストをライブラリのパスで絞り込みます。
+
+use crate::Result;
+
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+
+use std::path::MAIN_SEPARATOR;
+
+/// `*` などがディレクトリの区切りを越えないようにする (`**` は越える)
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// ライブラリのパスに対するパターン一つを表す列挙体です。
+#[derive(Debug)]
+enum Matcher {
+    /// パスの一部に含まれていればマッチする
+    Substring(String),
+
+    /// パス全体がグロブにマッチすればマッチする
+    Glob(Pattern),
+
+    /// パスの一部が正規表現にマッチすればマッチする
+    Regex(Regex),
+}
+
+/// 実行するテストを選ぶフィルタです。
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// いずれかにマッチしたテストを実行する (空なら全て実行する)
+    includes: Vec<Matcher>,
+
+    /// いずれかにマッチしたテストは実行しない
+    excludes: Vec<Matcher>,
+}
+
+impl Matcher {
+    fn new(pattern: &str, regex: bool) -> Result<Matcher> {
+        if regex {
+            let re = Regex::new(pattern)
+                .map_err(|e| format!("invalid regex pattern `{}`: {}", pattern, e))?;
+            return Ok(Matcher::Regex(re));
+        }
+
+        if pattern.contains(['*', '?', '[']) {
+            let glob = Pattern::new(pattern)
+                .map_err(|e| format!("invalid glob pattern `{}`: {}", pattern, e))?;
+            Ok(Matcher::Glob(glob))
+        } else {
+            Ok(Matcher::Substring(pattern.to_string()))
+        }
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        match self {
+            Matcher::Substring(s) => path.contains(&**s),
+            Matcher::Glob(glob) => glob.matches_with(path, GLOB_OPTIONS),
+            Matcher::Regex(re) => re.is_match(path),
+        }
+    }
+}
+
+impl Filter {
+    /// フィルタを作成します。
+    ///
+    /// `regex` が真なら全てのパターンを正規表現として扱います。そうでなければ `*`, `?`, `[`
+    /// を含むパターンはグロブ、それ以外は部分文字列として扱います。
+    pub fn new(includes: &[String], excludes: &[String], regex: bool) -> Result<Filter> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| Matcher::new(pattern, regex))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        Ok(Filter {
+            includes: compile(includes)?,
+            excludes: compile(excludes)?,
+        })
+    }
+
+    /// 実行しないテストのパターンを追加します。
+    pub fn exclude(&mut self, patterns: &[String], regex: bool) -> Result<()> {
+        for pattern in patterns {
+            self.excludes.push(Matcher::new(pattern, regex)?);
+        }
+
+        Ok(())
+    }
+
+    /// ライブラリのルートからの相対パス `path` のライブラリをテストするかどうかを返します。
+    ///
+    /// OS によらず同じパターンが使えるよう、区切り文字は `/` に揃えてから照合します。
+    pub fn is_match(&self, path: &str) -> bool {
+        let path = path.replace(MAIN_SEPARATOR, "/");
+        let included = self.includes.is_empty() || self.includes.iter().any(|m| m.is_match(&path));
+
+        included && !self.excludes.iter().any(|m| m.is_match(&path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    fn build(includes: &[&str], excludes: &[&str], regex: bool) -> Filter {
+        Filter::new(&patterns(includes), &patterns(excludes), regex).unwrap()
+    }
+
+    #[test]
+    fn patterns_are_classified_by_their_characters() {
+        assert!(matches!(
+            Matcher::new("graph", false).unwrap(),
+            Matcher::Substring(_)
+        ));
+        for pattern in &["graph/*", "a?b", "[ab].hpp"] {
+            assert!(matches!(
+                Matcher::new(pattern, false).unwrap(),
+                Matcher::Glob(_)
+            ));
+        }
+        assert!(matches!(
+            Matcher::new("graph/*", true).unwrap(),
+            Matcher::Regex(_)
+        ));
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        assert!(Matcher::new("[a", false).is_err());
+        assert!(Matcher::new("(a", true).is_err());
+    }
+
+    #[test]
+    fn substring_matches_any_part_of_the_path() {
+        let filter = build(&["tree"], &[], false);
+        assert!(filter.is_match("graph/tree/lca.hpp"));
+        assert!(filter.is_match("ds/segtree.hpp"));
+        assert!(!filter.is_match("math/modint.hpp"));
+    }
+
+    #[test]
+    fn glob_matches_the_whole_path_without_crossing_separators() {
+        let filter = build(&["graph/*.hpp"], &[], false);
+        assert!(filter.is_match("graph/dijkstra.hpp"));
+        assert!(!filter.is_match("graph/tree/lca.hpp"));
+        assert!(!filter.is_match("old/graph/dijkstra.hpp"));
+
+        let filter = build(&["graph/**/*.hpp"], &[], false);
+        assert!(filter.is_match("graph/tree/lca.hpp"));
+    }
+
+    #[test]
+    fn regex_matches_any_part_of_the_path() {
+        let filter = build(&["^graph/.*\\.hpp$"], &[], true);
+        assert!(filter.is_match("graph/tree/lca.hpp"));
+        assert!(!filter.is_match("old/graph/lca.hpp"));
+        assert!(build(&["tree/l"], &[], true).is_match("graph/tree/lca.hpp"));
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let filter = build(&["graph"], &["tree"], false);
+        assert!(filter.is_match("graph/dijkstra.hpp"));
+        assert!(!filter.is_match("graph/tree/lca.hpp"));
+
+        let filter = build(&[], &["*.hpp"], false);
+        assert!(!filter.is_match("modint.hpp"));
+        assert!(filter.is_match("modint.rs"));
+    }
+
+    #[test]
+    fn separators_are_normalized_before_matching() {
+        let path = format!("graph{}tree{}lca.hpp", MAIN_SEPARATOR, MAIN_SEPARATOR);
+        assert!(build(&["graph/tree"], &[], false).is_match(&path));
+        assert!(build(&["graph/*/lca.hpp"], &[], false).is_match(&path));
+        assert!(build(&["^graph/tree/"], &[], true).is_match(&path));
+    }
+}
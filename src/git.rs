@@ -0,0 +1,44 @@
+//! git に変更されたファイルを問い合わせます。
+
+use crate::Result;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// `root` 以下で、リビジョン `rev` から変更されたファイルを列挙します。
+///
+/// 作業ツリーの内容 (ステージされていない変更を含む) と `rev` を比較します。まだ追跡されて
+/// いない新しいファイルも変更されたものとして扱います。
+pub fn changed_files(root: &Path, rev: &str) -> Result<Vec<PathBuf>> {
+    let diff = git(
+        root,
+        &["diff", "--name-only", "--relative", "-z", rev, "--"],
+    )?;
+    let untracked = git(root, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+
+    let files = diff
+        .split('\0')
+        .chain(untracked.split('\0'))
+        .filter(|file| !file.is_empty())
+        .map(|file| root.join(file))
+        .collect();
+
+    Ok(files)
+}
+
+/// `root` で git を実行し、その標準出力を返します。
+fn git(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {} failed: {}", args.join(" "), stderr.trim()).into());
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
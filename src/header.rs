@@ -0,0 +1,74 @@
+//! ライブラリのヘッダが単体でコンパイルできる (自己完結している) かを確かめます。
+//!
+//! テストプロジェクトが先に `<vector>` などをインクルードしているおかげでコンパイルできている
+//! ヘッダは、コンテストで単体で貼り付けたときにコンパイルできないことがあります。
+
+use crate::process;
+use crate::{Judgement, TestResult};
+
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+/// 単体でのコンパイルを確かめるヘッダの拡張子
+const HEADER_EXTENSIONS: &[&str] = &["hpp", "h", "hh", "hxx"];
+
+/// ヘッダを単体でコンパイルしてみる検査です。
+#[derive(Debug)]
+pub struct HeaderCheck {
+    compiler: String,
+    flags: Vec<String>,
+}
+
+impl HeaderCheck {
+    pub fn new(compiler: String, flags: Vec<String>) -> HeaderCheck {
+        HeaderCheck { compiler, flags }
+    }
+
+    /// `path` が検査の対象となるヘッダかどうかを返します。
+    pub fn is_header(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| HEADER_EXTENSIONS.contains(&extension))
+    }
+
+    /// `header` だけをインクルードする翻訳単位を、構文チェックのみでコンパイルします。
+    ///
+    /// 翻訳単位は空の標準入力とし、`-include` でヘッダを読み込ませます。
+    pub fn check(&self, header: &Path) -> io::Result<Judgement> {
+        let mut cmd = Command::new(&self.compiler);
+        cmd.args(&self.flags)
+            .args(["-fsyntax-only", "-x", "c++", "-include"])
+            .arg(header)
+            .arg("-")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = header.parent() {
+            cmd.current_dir(dir);
+        }
+
+        let start = Instant::now();
+        let finished = process::run(&mut cmd, None).map_err(|e| {
+            let msg = format!("failed to run compiler `{}`: {}", self.compiler, e);
+            io::Error::new(e.kind(), msg)
+        })?;
+        let result = match finished.status {
+            Some(status) if status.success() => TestResult::Succeeded,
+            _ => TestResult::NotSelfContained,
+        };
+
+        let mut stderr = finished.stdout;
+        stderr.extend(finished.stderr);
+
+        Ok(Judgement {
+            result,
+            exit_code: finished.status.and_then(|status| status.code()),
+            duration: start.elapsed(),
+            stdout: Vec::new(),
+            stderr,
+            detail: None,
+        })
+    }
+}
@@ -0,0 +1,199 @@
+//! テスト結果を JSON 形式で書き出します。
+
+use crate::{path_root_removed, Judgement, Summary, Test, TestResult};
+
+use serde::Serialize;
+
+use std::borrow::Cow;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::time::Duration;
+
+/// テスト一つの結果を表す JSON オブジェクトです。
+#[derive(Serialize)]
+pub struct TestRecord<'a> {
+    /// ライブラリのルートからのライブラリの相対パス
+    library: String,
+
+    /// ライブラリのルートからのテストプロジェクトの相対パス
+    project: String,
+
+    /// 追加のテストプロジェクトの名前 (主なテストプロジェクトなら `null`)
+    variant: Option<&'a str>,
+
+    /// マトリクスの組み合わせの名前 (マトリクスを使わなければ `null`)
+    combination: Option<&'a str>,
+
+    result: TestResult,
+
+    /// テストの終了コード (実行しなかった場合やシグナルで終了した場合は `null`)
+    exit_code: Option<i32>,
+
+    /// 実行にかかった時間 (秒)
+    duration: f64,
+
+    stdout: Cow<'a, str>,
+    stderr: Cow<'a, str>,
+
+    /// 失敗の理由の抜粋 (サニタイザの報告など、なければ `null`)
+    detail: Option<&'a str>,
+}
+
+/// 全体の集計結果を表す JSON オブジェクトです。
+#[derive(Serialize)]
+pub struct SummaryRecord<'a> {
+    total: usize,
+
+    #[serde(flatten)]
+    counts: &'a Summary,
+
+    /// 全体の実行にかかった時間 (秒)
+    duration: f64,
+}
+
+/// `--format json` で出力する、実行全体を表す JSON オブジェクトです。
+#[derive(Serialize)]
+struct Document<'a> {
+    library_root: &'a Path,
+    tests: Vec<TestRecord<'a>>,
+    summary: SummaryRecord<'a>,
+}
+
+/// `list` サブコマンドで出力する、見つかったテスト一つを表す JSON オブジェクトです。
+#[derive(Serialize)]
+pub struct ListRecord {
+    /// ライブラリのルートからのライブラリの相対パス
+    library: String,
+
+    /// ライブラリのルートからのテストプロジェクトの相対パス
+    project: String,
+
+    /// 追加のテストプロジェクトの名前 (主なテストプロジェクトなら `null`)
+    variant: Option<String>,
+
+    /// テストプロジェクトが存在するかどうか
+    exists: bool,
+}
+
+/// `list --format json` で出力する JSON オブジェクトです。
+#[derive(Serialize)]
+struct ListDocument<'a> {
+    library_root: &'a Path,
+    tests: Vec<ListRecord>,
+}
+
+/// `--format jsonl` で一行ずつ出力するイベントです。
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    Start { library_root: &'a Path },
+    Test(TestRecord<'a>),
+    Summary(SummaryRecord<'a>),
+}
+
+impl<'a> TestRecord<'a> {
+    pub fn new(test: &'a Test, judgement: &'a Judgement, root: &Path) -> TestRecord<'a> {
+        TestRecord {
+            library: path_root_removed(&test.library, root),
+            project: path_root_removed(&test.project, root),
+            variant: test.variant.as_deref(),
+            combination: test.combination.as_deref(),
+            result: judgement.result,
+            exit_code: judgement.exit_code,
+            duration: judgement.duration.as_secs_f64(),
+            stdout: String::from_utf8_lossy(&judgement.stdout),
+            stderr: String::from_utf8_lossy(&judgement.stderr),
+            detail: judgement.detail.as_deref(),
+        }
+    }
+}
+
+impl ListRecord {
+    pub fn new(test: &Test, root: &Path) -> ListRecord {
+        ListRecord {
+            library: path_root_removed(&test.library, root),
+            project: path_root_removed(&test.project, root),
+            variant: test.variant.clone(),
+            exists: test.project.exists(),
+        }
+    }
+}
+
+impl<'a> SummaryRecord<'a> {
+    pub fn new(counts: &'a Summary, duration: Duration) -> SummaryRecord<'a> {
+        SummaryRecord {
+            total: counts.total(),
+            counts,
+            duration: duration.as_secs_f64(),
+        }
+    }
+}
+
+/// 実行全体の結果を一つの JSON ドキュメントとして出力します。
+pub fn print_document(
+    library_root: &Path,
+    tests: Vec<TestRecord>,
+    summary: SummaryRecord,
+) -> io::Result<()> {
+    let document = Document {
+        library_root,
+        tests,
+        summary,
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer_pretty(&mut stdout, &document)?;
+    writeln!(stdout)
+}
+
+/// 見つかったテストの一覧を一つの JSON ドキュメントとして出力します。
+pub fn print_list(library_root: &Path, tests: Vec<ListRecord>) -> io::Result<()> {
+    let document = ListDocument {
+        library_root,
+        tests,
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer_pretty(&mut stdout, &document)?;
+    writeln!(stdout)
+}
+
+/// 見つかったテストの一覧を一行に一つずつ出力します。
+pub fn print_list_lines(tests: &[ListRecord]) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for test in tests {
+        serde_json::to_writer(&mut stdout, test)?;
+        writeln!(stdout)?;
+    }
+
+    Ok(())
+}
+
+/// テストの開始を表すイベントを出力します。
+pub fn print_start_event(library_root: &Path) -> io::Result<()> {
+    print_event(&Event::Start { library_root })
+}
+
+/// テスト一つの結果を表すイベントを出力します。
+pub fn print_test_event(record: TestRecord) -> io::Result<()> {
+    print_event(&Event::Test(record))
+}
+
+/// 全体の集計結果を表すイベントを出力します。
+pub fn print_summary_event(summary: SummaryRecord) -> io::Result<()> {
+    print_event(&Event::Summary(summary))
+}
+
+fn print_event(event: &Event) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer(&mut stdout, event)?;
+    writeln!(stdout)?;
+
+    // 読み手がテストの終了を待たずに処理できるよう、一行ごとに吐き出す
+    stdout.flush()
+}
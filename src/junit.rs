@@ -0,0 +1,181 @@
+//! テスト結果を JUnit XML 形式で書き出します。
+
+use crate::{Judgement, TestResult};
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::time::Duration;
+
+/// JUnit XML 上でのテストスイートの名前
+const SUITE_NAME: &str = "procon-lib-tester";
+
+/// JUnit XML 上でのテストケース一つを表す構造体です。
+pub struct Case<'a> {
+    /// ライブラリのルートからの相対パス
+    pub name: String,
+
+    pub judgement: &'a Judgement,
+}
+
+/// `cases` を JUnit XML 形式で `path` に書き出します。
+pub fn write(path: &str, cases: &[Case]) -> io::Result<()> {
+    fs::write(path, render(cases))
+}
+
+fn render(cases: &[Case]) -> String {
+    let count = |pred: fn(TestResult) -> bool| {
+        cases
+            .iter()
+            .filter(|case| pred(case.judgement.result))
+            .count()
+    };
+    let failures = count(|r| {
+        r == TestResult::Failed
+            || r == TestResult::TimedOut
+            || r == TestResult::NotSelfContained
+            || r == TestResult::SanitizerError
+    });
+    let skipped = count(|r| r == TestResult::NotFound);
+    let time: Duration = cases.iter().map(|case| case.judgement.duration).sum();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<testsuites>\n");
+    let _ = writeln!(
+        xml,
+        r#"  <testsuite name="{}" tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}">"#,
+        SUITE_NAME,
+        cases.len(),
+        failures,
+        skipped,
+        time.as_secs_f64()
+    );
+
+    for case in cases {
+        render_case(&mut xml, case);
+    }
+
+    xml.push_str("  </testsuite>\n");
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn render_case(xml: &mut String, case: &Case) {
+    let judgement = case.judgement;
+    let _ = writeln!(
+        xml,
+        r#"    <testcase name="{}" classname="{}" time="{:.3}">"#,
+        escape(&case.name),
+        escape(&classname(&case.name)),
+        judgement.duration.as_secs_f64()
+    );
+
+    match judgement.result {
+        TestResult::Succeeded | TestResult::Cached | TestResult::Flaky => {}
+        TestResult::Failed => xml.push_str("      <failure message=\"test failed\"/>\n"),
+        TestResult::TimedOut => xml.push_str("      <failure message=\"test timed out\"/>\n"),
+        TestResult::NotSelfContained => {
+            xml.push_str("      <failure message=\"header is not self-contained\"/>\n")
+        }
+        TestResult::SanitizerError => {
+            let detail = judgement.detail.as_deref().unwrap_or_default();
+            let _ = writeln!(
+                xml,
+                r#"      <failure message="sanitizer reported an error">{}</failure>"#,
+                escape(detail)
+            );
+        }
+        TestResult::NotFound => {
+            xml.push_str("      <skipped message=\"test project not found\"/>\n")
+        }
+    }
+
+    if !judgement.stderr.is_empty() {
+        let stderr = String::from_utf8_lossy(&judgement.stderr);
+        let _ = writeln!(xml, "      <system-err>{}</system-err>", escape(&stderr));
+    }
+
+    xml.push_str("    </testcase>\n");
+}
+
+/// テストケースの所属するクラス名として、ライブラリのあるディレクトリを `.` 区切りにしたものを
+/// 返します。ルート直下のライブラリはテストスイートの名前を使います。
+fn classname(name: &str) -> String {
+    match name.rfind(['/', '\\']) {
+        Some(pos) => name[..pos].replace(['/', '\\'], "."),
+        None => SUITE_NAME.to_string(),
+    }
+}
+
+/// XML のテキストや属性値に埋め込めるようにエスケープします。
+///
+/// XML 1.0 では使えない制御文字は取り除きます。色付けのためのエスケープシーケンスは、
+/// シーケンス全体を取り除きます。
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\u{1b}' => skip_escape_sequence(&mut chars),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(ch),
+            ch if ch < ' ' => {}
+            ch => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
+
+/// ESC に続くエスケープシーケンスの残りを読み飛ばします。
+fn skip_escape_sequence(chars: &mut Peekable<Chars>) {
+    if chars.peek() != Some(&'[') {
+        chars.next();
+        return;
+    }
+
+    // CSI シーケンスは '@' から '~' までの文字で終わる
+    chars.next();
+    for ch in chars {
+        if ('@'..='~').contains(&ch) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_special_characters() {
+        assert_eq!(
+            escape(r#"<a href="x">&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn escape_strips_color_sequences() {
+        assert_eq!(escape("\u{1b}[1;31mFAILURE\u{1b}[0m done"), "FAILURE done");
+        assert_eq!(escape("\u{1b}cx"), "x");
+    }
+
+    #[test]
+    fn escape_drops_invalid_control_characters() {
+        assert_eq!(escape("a\u{0}b\u{7}c\td\r\ne"), "abc\td\r\ne");
+    }
+
+    #[test]
+    fn classname_uses_directory() {
+        assert_eq!(classname("graph/tree/lca.hpp"), "graph.tree");
+        assert_eq!(classname("modint.hpp"), SUITE_NAME);
+    }
+}
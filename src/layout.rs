@@ -0,0 +1,451 @@
+//! ライブラリとそのテストプロジェクトの対応付けを扱います。
+
+use crate::config::ProjectsConfig;
+use crate::deps;
+use crate::Result;
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// テストプロジェクトを置く場所の規則を表す列挙体です。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutKind {
+    /// `foo.hpp` のテストプロジェクトを隣の `foo.<拡張子>` とする
+    Sibling,
+
+    /// `foo/bar.hpp` のテストプロジェクトを `<ディレクトリ>/foo/bar` とする
+    Mirror,
+}
+
+/// 拡張子が指定されなかった場合にライブラリとして扱うファイルの拡張子
+const DEFAULT_LIBRARY_EXTENSION: &str = "hpp";
+
+/// `mirror` の規則でディレクトリが指定されなかった場合に使うディレクトリ
+const DEFAULT_MIRROR_DIR: &str = "tests";
+
+/// ライブラリからテストプロジェクトのディレクトリを決める規則です。
+#[derive(Debug)]
+pub struct Layout {
+    library_root: PathBuf,
+
+    /// ライブラリとして扱うファイルの拡張子 (`.` なし)
+    library_extensions: Vec<String>,
+
+    rule: Rule,
+
+    /// 規則より優先する、ライブラリ → テストプロジェクトの対応表 (どちらも正規化した絶対パス)
+    table: BTreeMap<PathBuf, PathBuf>,
+}
+
+/// ライブラリのテストプロジェクト一つを表す構造体です。
+#[derive(Debug)]
+pub struct Project {
+    pub dir: PathBuf,
+
+    /// 追加のテストプロジェクトの名前 (規則で決まるテストプロジェクトなら `None`)
+    pub variant: Option<String>,
+}
+
+#[derive(Debug)]
+enum Rule {
+    Sibling { extension: String },
+    Mirror { dir: PathBuf },
+}
+
+impl Layout {
+    /// 設定から規則を作ります。`extension` は `sibling` の規則で使う拡張子です。
+    pub fn new(
+        library_root: &Path,
+        library_extensions: Option<&[String]>,
+        config: &ProjectsConfig,
+        extension: String,
+    ) -> Result<Layout> {
+        let library_extensions = match library_extensions {
+            Some(extensions) => extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_string())
+                .collect(),
+            None => vec![DEFAULT_LIBRARY_EXTENSION.to_string()],
+        };
+
+        let rule = match config.layout.unwrap_or(LayoutKind::Sibling) {
+            LayoutKind::Sibling => Rule::Sibling { extension },
+            LayoutKind::Mirror => {
+                let dir = config.dir.as_deref().unwrap_or(DEFAULT_MIRROR_DIR);
+                let normalized = deps::normalize(&library_root.join(dir));
+                // ルート自体をテストプロジェクト用にするとライブラリが見つからなくなる
+                if normalized == deps::normalize(library_root) {
+                    return Err(format!("invalid project directory: {}", dir).into());
+                }
+                Rule::Mirror { dir: normalized }
+            }
+        };
+
+        let table = config
+            .map
+            .iter()
+            .map(|(library, project)| {
+                let library = deps::normalize(&library_root.join(library));
+                let project = deps::normalize(&library_root.join(project));
+                (library, project)
+            })
+            .collect();
+
+        Ok(Layout {
+            library_root: library_root.to_path_buf(),
+            library_extensions,
+            rule,
+            table,
+        })
+    }
+
+    /// `path` がライブラリとして扱うファイルかどうかを返します。
+    pub fn is_library(&self, path: &Path) -> bool {
+        let extension = match path.extension() {
+            Some(extension) => extension,
+            None => return false,
+        };
+
+        path.is_file()
+            && self
+                .library_extensions
+                .iter()
+                .any(|library| **library == *extension)
+    }
+
+    /// ライブラリ `library` のテストプロジェクトのディレクトリを返します。
+    fn project_of(&self, library: &Path) -> PathBuf {
+        if let Some(project) = self.table.get(&deps::normalize(library)) {
+            return project.clone();
+        }
+
+        match &self.rule {
+            Rule::Sibling { extension } => library.with_extension(extension),
+            Rule::Mirror { dir } => {
+                let relative = library.strip_prefix(&self.library_root).unwrap_or(library);
+                dir.join(relative.with_extension(""))
+            }
+        }
+    }
+
+    /// ライブラリ `library` のテストプロジェクトを全て列挙します。
+    ///
+    /// 規則で決まるテストプロジェクト `foo.test` に加え、`foo.test.large` のように名前の後ろに
+    /// `.` 区切りで付け足したディレクトリと、`foo.tests` のように名前に `s` を付けたディレクトリの
+    /// 中の各ディレクトリも、それぞれ追加のテストプロジェクトとします。追加のテストプロジェクトには、
+    /// 付け足した部分またはディレクトリ名を `variant` として付けます。
+    ///
+    /// ただし、他のライブラリの規則や対応表で決まるテストプロジェクト (`foo.fast.hpp` に対する
+    /// `foo.fast.test` など) は追加のテストプロジェクトとしません。
+    ///
+    /// テストプロジェクトが一つもなければ、規則で決まる (存在しない) テストプロジェクトを返します。
+    ///
+    /// ディレクトリの中身は `cache` に覚えておき、同じディレクトリは二度読みません。
+    pub fn projects_of(&self, library: &Path, cache: &mut DirCache) -> Vec<Project> {
+        let primary = self.project_of(library);
+        let mut projects = Vec::new();
+        if primary.exists() {
+            projects.push(Project {
+                dir: primary.clone(),
+                variant: None,
+            });
+        }
+
+        if let (Some(parent), Some(name)) = (primary.parent(), primary.file_name()) {
+            let name = name.to_string_lossy();
+            let prefix = format!("{}.", name);
+            for (dir, dir_name) in cache.subdirs(parent) {
+                if let Some(variant) = dir_name.strip_prefix(&prefix) {
+                    if !self.is_primary_project(dir) {
+                        projects.push(Project {
+                            dir: dir.clone(),
+                            variant: Some(variant.to_string()),
+                        });
+                    }
+                }
+            }
+
+            for (dir, dir_name) in cache.subdirs(&self.container_of(library)) {
+                if !self.is_primary_project(dir) {
+                    projects.push(Project {
+                        dir: dir.clone(),
+                        variant: Some(dir_name.clone()),
+                    });
+                }
+            }
+        }
+
+        if projects.is_empty() {
+            projects.push(Project {
+                dir: primary,
+                variant: None,
+            });
+        }
+
+        projects
+    }
+
+    /// ライブラリ `library` の追加のテストプロジェクトを置くディレクトリ (`foo.tests`) を返します。
+    pub fn container_of(&self, library: &Path) -> PathBuf {
+        let primary = self.project_of(library);
+        match primary.file_name() {
+            Some(name) => {
+                let mut name = name.to_os_string();
+                name.push("s");
+                primary.with_file_name(name)
+            }
+            None => primary,
+        }
+    }
+
+    /// `dir` が、存在するいずれかのライブラリの規則や対応表で決まるテストプロジェクトかどうかを
+    /// 返します。
+    pub fn is_primary_project(&self, dir: &Path) -> bool {
+        let dir = deps::normalize(dir);
+        if self.table.values().any(|project| *project == dir) {
+            return true;
+        }
+
+        // 規則を逆にたどって、このディレクトリをテストプロジェクトとするライブラリの拡張子を除いた
+        // パスを求める
+        let stem = match &self.rule {
+            Rule::Sibling { extension } => {
+                if dir.extension().is_none_or(|ext| *ext != **extension) {
+                    return false;
+                }
+                dir.with_extension("")
+            }
+            Rule::Mirror { dir: projects } => match dir.strip_prefix(projects) {
+                Ok(relative) => self.library_root.join(relative),
+                Err(_) => return false,
+            },
+        };
+
+        self.library_extensions.iter().any(|extension| {
+            let mut library = OsString::from(&stem);
+            library.push(".");
+            library.push(extension);
+            let library = PathBuf::from(library);
+            self.is_library(&library) && deps::normalize(&self.project_of(&library)) == dir
+        })
+    }
+
+    /// `dir` がテストプロジェクトだけを置くディレクトリで、ライブラリを探す必要がないかどうかを
+    /// 返します。
+    pub fn is_project_tree(&self, dir: &Path) -> bool {
+        match &self.rule {
+            Rule::Sibling { .. } => false,
+            Rule::Mirror { dir: projects } => deps::normalize(dir) == *projects,
+        }
+    }
+}
+
+/// 読んだディレクトリの中身を覚えておくキャッシュです。
+///
+/// 同じディレクトリに多数のライブラリがあっても、テストプロジェクトを探すためにそのディレクトリを
+/// ライブラリごとに読み直さずに済ませます。その後のファイルの変更は反映されないので、一度の列挙の
+/// 間だけ使います。
+#[derive(Debug, Default)]
+pub struct DirCache {
+    /// ディレクトリ → その中のディレクトリとその名前
+    subdirs: BTreeMap<PathBuf, Vec<(PathBuf, String)>>,
+}
+
+impl DirCache {
+    /// `dir` の中のディレクトリとその名前を名前順に返します。
+    fn subdirs(&mut self, dir: &Path) -> &[(PathBuf, String)] {
+        self.subdirs
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| subdirs(dir))
+    }
+}
+
+/// `dir` の中のディレクトリとその名前を名前順に列挙します。`dir` が読めなければ空とします。
+fn subdirs(dir: &Path) -> Vec<(PathBuf, String)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut dirs: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            (entry.path(), name)
+        })
+        .collect();
+    dirs.sort();
+
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    /// 一時ディレクトリに `files` のファイルと `dirs` のディレクトリを作り、そのディレクトリを
+    /// 返します。
+    fn fixture(name: &str, files: &[&str], dirs: &[&str]) -> PathBuf {
+        let root = env::temp_dir().join(format!("procon-lib-tester-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in dirs {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        root
+    }
+
+    fn layout(root: &Path, config: &ProjectsConfig) -> Layout {
+        Layout::new(root, None, config, "test".to_string()).unwrap()
+    }
+
+    /// `library` のテストプロジェクトを、ルートからのパスと追加のテストプロジェクトの名前の組で
+    /// 返します。
+    fn projects(layout: &Layout, root: &Path, library: &str) -> Vec<(String, Option<String>)> {
+        layout
+            .projects_of(&root.join(library), &mut DirCache::default())
+            .into_iter()
+            .map(|project| {
+                let dir = project.dir.strip_prefix(root).unwrap();
+                (dir.to_string_lossy().replace('\\', "/"), project.variant)
+            })
+            .collect()
+    }
+
+    fn variant(dir: &str, variant: Option<&str>) -> (String, Option<String>) {
+        (dir.to_string(), variant.map(str::to_string))
+    }
+
+    #[test]
+    fn sibling_projects_and_variants() {
+        let root = fixture(
+            "sibling",
+            &["foo.hpp", "foo.fast.hpp", "bar.hpp"],
+            &[
+                "foo.test",
+                "foo.test.large",
+                "foo.test.fast",
+                "foo.tests/random",
+                "foo.fast.test",
+            ],
+        );
+        let layout = layout(&root, &ProjectsConfig::default());
+
+        assert_eq!(
+            projects(&layout, &root, "foo.hpp"),
+            [
+                variant("foo.test", None),
+                variant("foo.test.fast", Some("fast")),
+                variant("foo.test.large", Some("large")),
+                variant("foo.tests/random", Some("random")),
+            ]
+        );
+        assert_eq!(
+            projects(&layout, &root, "foo.fast.hpp"),
+            [variant("foo.fast.test", None)]
+        );
+        assert_eq!(
+            projects(&layout, &root, "bar.hpp"),
+            [variant("bar.test", None)]
+        );
+        assert_eq!(
+            layout.container_of(&root.join("foo.hpp")),
+            root.join("foo.tests")
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn mirror_does_not_claim_projects_of_other_libraries() {
+        let root = fixture(
+            "mirror",
+            &["graph/dij.hpp", "graph/dij.fast.hpp"],
+            &[
+                "tests/graph/dij",
+                "tests/graph/dij.large",
+                "tests/graph/dij.fast",
+            ],
+        );
+        let config = ProjectsConfig {
+            layout: Some(LayoutKind::Mirror),
+            ..ProjectsConfig::default()
+        };
+        let layout = layout(&root, &config);
+
+        assert_eq!(
+            projects(&layout, &root, "graph/dij.hpp"),
+            [
+                variant("tests/graph/dij", None),
+                variant("tests/graph/dij.large", Some("large")),
+            ]
+        );
+        assert_eq!(
+            projects(&layout, &root, "graph/dij.fast.hpp"),
+            [variant("tests/graph/dij.fast", None)]
+        );
+        assert!(layout.is_primary_project(&root.join("tests/graph/dij.fast")));
+        assert!(!layout.is_primary_project(&root.join("tests/graph/dij.large")));
+        assert!(layout.is_project_tree(&root.join("tests")));
+        assert!(!layout.is_project_tree(&root.join("graph")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn table_overrides_the_rule() {
+        let root = fixture(
+            "table",
+            &["modint.hpp", "modint.cpp"],
+            &[
+                "modint.test",
+                "checks/modint",
+                "checks/modint.big",
+                "checks/modint-cpp",
+            ],
+        );
+        let config = ProjectsConfig {
+            map: vec![
+                ("modint.hpp", "checks/modint"),
+                ("modint.cpp", "checks/modint-cpp"),
+            ]
+            .into_iter()
+            .map(|(library, project)| (library.to_string(), project.to_string()))
+            .collect(),
+            ..ProjectsConfig::default()
+        };
+        let extensions = ["hpp".to_string(), "cpp".to_string()];
+        let layout = Layout::new(&root, Some(&extensions), &config, "test".to_string()).unwrap();
+
+        assert_eq!(
+            projects(&layout, &root, "modint.hpp"),
+            [
+                variant("checks/modint", None),
+                variant("checks/modint.big", Some("big")),
+            ]
+        );
+        assert_eq!(
+            projects(&layout, &root, "modint.cpp"),
+            [variant("checks/modint-cpp", None)]
+        );
+        assert!(layout.is_primary_project(&root.join("checks/modint-cpp")));
+        // 対応表に書かれたライブラリは、規則で決まるディレクトリをテストプロジェクトとしない
+        assert!(!layout.is_primary_project(&root.join("modint.test")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
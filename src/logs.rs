@@ -0,0 +1,33 @@
+//! テストごとに捕捉した出力をログファイルとして書き出します。
+
+use crate::Judgement;
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// テスト `name` (ライブラリのルートからの相対パス) のログを `dir/<name>.log` に書き出し、
+/// 書き出したファイルのパスを返します。
+pub fn write(dir: &Path, name: &str, judgement: &Judgement) -> io::Result<PathBuf> {
+    let path = dir.join(format!("{}.log", name));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = io::BufWriter::new(fs::File::create(&path)?);
+    writeln!(file, "library: {}", name)?;
+    writeln!(file, "result: {}", judgement.result)?;
+    if let Some(code) = judgement.exit_code {
+        writeln!(file, "exit code: {}", code)?;
+    }
+    writeln!(file, "duration: {:.3}s", judgement.duration.as_secs_f64())?;
+
+    writeln!(file, "===== stdout =====")?;
+    file.write_all(&judgement.stdout)?;
+    writeln!(file, "===== stderr =====")?;
+    file.write_all(&judgement.stderr)?;
+    file.flush()?;
+
+    Ok(path)
+}
@@ -1,15 +1,52 @@
+mod cache;
+mod cli;
+mod config;
+mod coverage;
+mod deps;
+mod filter;
+mod git;
+mod header;
+mod json;
+mod junit;
+mod layout;
+mod logs;
+mod matrix;
+mod process;
+mod runner;
+mod scaffold;
+mod watch;
+
+use cache::ResultCache;
+use cli::{CheckHeadersArgs, Cli, Command, FilterArgs, RunArgs};
 use colored_print::color::ConsoleColor as CC;
 use colored_print::colored_println;
+use config::Config;
+use deps::IncludeGraph;
+use filter::Filter;
+use header::HeaderCheck;
+use layout::{DirCache, Layout};
+use matrix::Matrix;
+use runner::{Runner, Runners};
+use serde::{Deserialize, Serialize};
 
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fmt;
 use std::fs;
 use std::io;
+use std::io::prelude::*;
 use std::path::{Path, PathBuf, MAIN_SEPARATOR};
-use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+/// プロジェクトごとにタイムアウトを上書きするためのファイルの名前。中身は秒数です。
+const PROJECT_TIMEOUT_FILE: &str = "tester_timeout";
+
 /// テスト一つを表す構造体です。
 #[derive(Debug)]
 struct Test {
@@ -18,72 +55,400 @@ struct Test {
 
     /// そのライブラリをテストするプロジェクトのディレクトリ (*.test)
     project: PathBuf,
+
+    /// 追加のテストプロジェクトの名前 (ライブラリの主なテストプロジェクトなら `None`)
+    variant: Option<String>,
+
+    /// 実行するマトリクスの組み合わせの名前 (マトリクスを使わなければ `None`)
+    combination: Option<String>,
 }
 
 /// テスト結果を表す列挙体です。
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 enum TestResult {
     Succeeded,
     Failed,
     NotFound,
+    TimedOut,
+
+    /// 前回成功したときから内容が変わっていないので実行しなかった
+    Cached,
+
+    /// 一度は失敗したが、実行し直したら成功した
+    Flaky,
+
+    /// ヘッダが単体ではコンパイルできなかった (`check-headers` でのみ使います)
+    NotSelfContained,
+
+    /// サニタイザが問題を報告した
+    SanitizerError,
+}
+
+/// 各テスト結果の件数を表す構造体です。
+#[derive(Debug, Default, Serialize)]
+struct Summary {
+    succeeded: usize,
+    failed: usize,
+    not_found: usize,
+    timed_out: usize,
+    cached: usize,
+    flaky: usize,
+    not_self_contained: usize,
+    sanitizer_error: usize,
+}
+
+/// テスト結果の出力形式を表す列挙体です。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    /// 人間が読むための色付きのテキスト
+    Text,
+
+    /// 実行全体を一つの JSON ドキュメントとして出力する
+    Json,
+
+    /// テストが終わるたびに一行ずつ JSON を出力する
+    #[serde(rename = "jsonl")]
+    JsonLines,
+}
+
+/// テストの実行方法を指定する構造体です。
+#[derive(Debug, Clone, Copy)]
+struct JudgeOptions {
+    /// procon-assistant に `--force` を渡すかどうか (ランナーによっては無視されます)
+    force: bool,
+
+    /// テストの出力を表示しないかどうか
+    simple: bool,
+
+    /// テストの標準出力・標準エラー出力を捕捉するかどうか (`simple` でも捕捉します)
+    capture: bool,
+
+    /// テスト一つあたりの制限時間 (プロジェクトごとの設定があればそちらを優先します)
+    timeout: Option<Duration>,
+
+    /// 失敗したテストを実行し直す最大の回数
+    retries: usize,
+}
+
+/// テストの実行と結果の出力に関する設定をまとめた構造体です。
+struct Session<'a> {
+    library_root: &'a Path,
+    runners: &'a Runners,
+
+    /// 組み合わせごとのランナー (組み合わせの指定されたテストに使います)
+    matrix: &'a Matrix,
+
+    cache: &'a ResultCache,
+    jobs: usize,
+    opts: JudgeOptions,
+    format: OutputFormat,
+
+    /// JUnit XML 形式の結果を書き出すファイル
+    junit: Option<String>,
+
+    /// テストごとの出力を書き出すディレクトリ
+    log_dir: Option<PathBuf>,
+
+    /// 実行の最後に表示する、時間のかかったテストの数 (0 なら表示しない)
+    slowest: usize,
+
+    colorize: bool,
+}
+
+/// ライブラリ一つについての、テストプロジェクトや組み合わせごとの結果をまとめたものです。
+struct LibraryResult<'a> {
+    library: &'a Path,
+    result: TestResult,
+
+    /// マトリクスの組み合わせごとにまとめた結果
+    combinations: BTreeMap<&'a str, TestResult>,
+
+    /// まとめたテストの数
+    tests: usize,
+}
+
+/// テストの結果と、実行中に捕捉した出力を表す構造体です。
+#[derive(Debug)]
+struct Judgement {
+    result: TestResult,
+
+    /// テストの終了コード (実行しなかった場合やシグナルで終了した場合は `None`)
+    exit_code: Option<i32>,
+
+    /// テストの実行にかかった時間
+    duration: Duration,
+
+    /// 捕捉した標準出力 (捕捉しなかった場合は空)
+    stdout: Vec<u8>,
+
+    /// 捕捉した標準エラー出力 (捕捉しなかった場合は空)
+    stderr: Vec<u8>,
+
+    /// 失敗の理由の抜粋 (サニタイザの最初の報告など)。出力を捕捉しなくても残します。
+    detail: Option<String>,
 }
 
 impl Test {
-    pub fn new(library: PathBuf) -> Test {
-        let project = library.with_extension("test");
-        Test { library, project }
+    /// ライブラリ `library` のテストを、テストプロジェクトごとに作ります。
+    pub fn of_library(library: PathBuf, layout: &Layout, cache: &mut DirCache) -> Vec<Test> {
+        layout
+            .projects_of(&library, cache)
+            .into_iter()
+            .map(|project| Test {
+                library: library.clone(),
+                project: project.dir,
+                variant: project.variant,
+                combination: None,
+            })
+            .collect()
     }
 
-    pub fn judge(&self, force: bool, simple: bool) -> io::Result<TestResult> {
+    /// 結果の表示などに使う、ライブラリのルートからの相対パスによるテストの名前を返します。
+    ///
+    /// 追加のテストプロジェクトのテストには、その名前を `foo.hpp [large]` のように付けます。
+    /// マトリクスの組み合わせは `foo.hpp @gcc-c++17` のように付けます。
+    pub fn name(&self, library_root: &Path) -> String {
+        let mut name = path_root_removed(&self.library, library_root);
+        if let Some(variant) = &self.variant {
+            name = format!("{} [{}]", name, variant);
+        }
+        if let Some(combination) = &self.combination {
+            name = format!("{} @{}", name, combination);
+        }
+
+        name
+    }
+
+    pub fn judge(
+        &self,
+        runner: &dyn Runner,
+        cache: &ResultCache,
+        opts: &JudgeOptions,
+    ) -> io::Result<Judgement> {
         if !self.project.exists() {
-            return Ok(TestResult::NotFound);
+            cache.forget(self);
+            return Ok(Judgement::without_run(TestResult::NotFound));
         }
 
-        let mut cmd = Command::new("procon-assistant");
-        cmd.arg("--quiet");
+        // 実行中に変更されたファイルで成功を記録しないよう、ハッシュは実行する前に計算する
+        let hash = cache.hash(self)?;
+        if cache.is_fresh(self, &hash) {
+            return Ok(Judgement::without_run(TestResult::Cached));
+        }
 
-        cmd.arg("run");
+        let opts = JudgeOptions {
+            timeout: self.project_timeout()?.or(opts.timeout),
+            ..*opts
+        };
 
-        if force {
-            cmd.arg("--force");
+        let mut judgement = runner.run(self, &opts)?;
+        for _ in 0..opts.retries {
+            if !judgement.has_failed() {
+                break;
+            }
+            judgement = judgement.retried(runner.run(self, &opts)?);
         }
+        cache.record(self, hash, judgement.result);
 
-        cmd.current_dir(&self.project)
-            .stdin(Stdio::null())
-            .stdout(Stdio::null());
+        Ok(judgement)
+    }
 
-        if simple {
-            cmd.stderr(Stdio::null());
-        } else {
-            cmd.stderr(Stdio::inherit());
+    /// プロジェクトに設定された制限時間を読み込みます。
+    fn project_timeout(&self) -> io::Result<Option<Duration>> {
+        let path = self.project.join(PROJECT_TIMEOUT_FILE);
+        if !path.exists() {
+            return Ok(None);
         }
 
-        let success = cmd.status()?.success();
+        let content = fs::read_to_string(&path)?;
+        parse_secs(content.trim()).map(Some).ok_or_else(|| {
+            let msg = format!("invalid timeout in {}: {}", path.display(), content.trim());
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        })
+    }
+}
+
+impl Judgement {
+    /// テストを実行しなかったときの結果を作ります。
+    fn without_run(result: TestResult) -> Judgement {
+        Judgement {
+            result,
+            exit_code: None,
+            duration: Duration::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            detail: None,
+        }
+    }
+
+    /// テストを実際に実行したかどうかを返します。
+    fn has_run(&self) -> bool {
+        match self.result {
+            TestResult::Succeeded
+            | TestResult::Failed
+            | TestResult::TimedOut
+            | TestResult::Flaky
+            | TestResult::NotSelfContained
+            | TestResult::SanitizerError => true,
+            TestResult::NotFound | TestResult::Cached => false,
+        }
+    }
+
+    /// テストが失敗したかどうか (実行し直す意味があるかどうか) を返します。
+    fn has_failed(&self) -> bool {
+        match self.result {
+            TestResult::Failed | TestResult::TimedOut | TestResult::SanitizerError => true,
+            TestResult::Succeeded
+            | TestResult::NotFound
+            | TestResult::Cached
+            | TestResult::Flaky
+            | TestResult::NotSelfContained => false,
+        }
+    }
+
+    /// 失敗した `self` の後に、実行し直した結果 `retry` を合わせます。
+    ///
+    /// 捕捉した出力は後から確認できるよう、全ての実行のものを順に並べて残します。
+    fn retried(self, retry: Judgement) -> Judgement {
+        let result = match retry.result {
+            TestResult::Succeeded => TestResult::Flaky,
+            result => result,
+        };
+
+        let mut stdout = self.stdout;
+        stdout.extend(retry.stdout);
+        let mut stderr = self.stderr;
+        stderr.extend(retry.stderr);
+
+        Judgement {
+            result,
+            exit_code: retry.exit_code,
+            duration: self.duration + retry.duration,
+            stdout,
+            stderr,
+            detail: retry.detail,
+        }
+    }
+}
+
+impl<'a> LibraryResult<'a> {
+    fn new(library: &'a Path) -> LibraryResult<'a> {
+        LibraryResult {
+            library,
+            result: TestResult::NotFound,
+            combinations: BTreeMap::new(),
+            tests: 0,
+        }
+    }
 
-        if success {
-            Ok(TestResult::Succeeded)
-        } else {
-            Ok(TestResult::Failed)
+    fn add(&mut self, test: &'a Test, result: TestResult) {
+        if self.tests == 0 || result.severity() > self.result.severity() {
+            self.result = result;
+        }
+        if let Some(combination) = &test.combination {
+            let worst = self.combinations.entry(combination).or_insert(result);
+            if result.severity() > worst.severity() {
+                *worst = result;
+            }
         }
+        self.tests += 1;
     }
 }
 
 impl TestResult {
+    /// 全ての結果
+    const ALL: &'static [TestResult] = &[
+        TestResult::Succeeded,
+        TestResult::Failed,
+        TestResult::NotFound,
+        TestResult::TimedOut,
+        TestResult::Cached,
+        TestResult::Flaky,
+        TestResult::NotSelfContained,
+        TestResult::SanitizerError,
+    ];
+
+    /// 結果の表示の最大の長さを返します。
+    fn max_label_len() -> usize {
+        TestResult::ALL
+            .iter()
+            .map(|result| result.to_string().len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 複数の結果をまとめるときに使う、結果の悪さを返します。
+    fn severity(self) -> u8 {
+        match self {
+            TestResult::NotFound => 0,
+            TestResult::Cached => 1,
+            TestResult::Succeeded => 2,
+            TestResult::Flaky => 3,
+            TestResult::TimedOut => 4,
+            TestResult::NotSelfContained => 5,
+            TestResult::Failed => 6,
+            TestResult::SanitizerError => 7,
+        }
+    }
+
     fn get_color(&self) -> CC {
         match *self {
             TestResult::Succeeded => CC::LightGreen,
             TestResult::Failed => CC::Red,
             TestResult::NotFound => CC::Yellow,
+            TestResult::TimedOut => CC::LightMagenta,
+            TestResult::Cached => CC::Cyan,
+            TestResult::Flaky => CC::LightYellow,
+            TestResult::NotSelfContained => CC::Magenta,
+            TestResult::SanitizerError => CC::LightRed,
         }
     }
 }
 
+impl Summary {
+    fn add(&mut self, result: TestResult) {
+        match result {
+            TestResult::Succeeded => self.succeeded += 1,
+            TestResult::Failed => self.failed += 1,
+            TestResult::NotFound => self.not_found += 1,
+            TestResult::TimedOut => self.timed_out += 1,
+            TestResult::Cached => self.cached += 1,
+            TestResult::Flaky => self.flaky += 1,
+            TestResult::NotSelfContained => self.not_self_contained += 1,
+            TestResult::SanitizerError => self.sanitizer_error += 1,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.succeeded
+            + self.failed
+            + self.not_found
+            + self.timed_out
+            + self.cached
+            + self.flaky
+            + self.not_self_contained
+            + self.sanitizer_error
+    }
+
+    /// 失敗として扱うテストがあったかどうかを返します。
+    fn has_failure(&self) -> bool {
+        self.failed + self.timed_out + self.not_self_contained + self.sanitizer_error != 0
+    }
+}
+
 impl fmt::Display for TestResult {
     fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             TestResult::Succeeded => write!(b, "SUCCESS"),
             TestResult::Failed => write!(b, "FAILURE"),
             TestResult::NotFound => write!(b, "MISSING"),
+            TestResult::TimedOut => write!(b, "TIMEOUT"),
+            TestResult::Cached => write!(b, "CACHED"),
+            TestResult::Flaky => write!(b, "FLAKY"),
+            TestResult::NotSelfContained => write!(b, "HEADER"),
+            TestResult::SanitizerError => write!(b, "SANITIZE"),
         }
     }
 }
@@ -97,73 +462,829 @@ fn path_root_removed(path: &Path, root: &Path) -> String {
     };
 
     if path.starts_with(&root) {
-        format!("{}", &path[root.len()..])
+        path[root.len()..].to_string()
     } else {
         path
     }
 }
 
+/// `--color` に与えられた値を解釈し、出力を色付けするかどうかを返します。
+fn parse_color(value: &str) -> Result<bool> {
+    match value {
+        "always" => Ok(true),
+        "none" => Ok(false),
+        "auto" => Ok(atty::is(atty::Stream::Stdout)),
+        value => Err(format!("unknown color setting: {}", value).into()),
+    }
+}
+
+/// 並列数を決めます。0 は利用可能な CPU 数を表します。
+fn resolve_jobs(jobs: usize) -> usize {
+    if jobs == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        jobs
+    }
+}
+
+/// 秒数を制限時間として解釈します。正の有限な値でなければ `None` を返します。
+fn secs_to_duration(secs: f64) -> Option<Duration> {
+    Some(secs)
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// 秒数を表す文字列を解釈します。小数も受け付けます。
+fn parse_secs(value: &str) -> Option<Duration> {
+    value.parse::<f64>().ok().and_then(secs_to_duration)
+}
+
 fn main() -> Result<()> {
-    let args = env::args().skip(1); // skip executable name
-    let mut colorize = atty::is(atty::Stream::Stdout);
-    let mut force = true;
-    let mut simple = false;
-    for arg in args {
-        match &*arg {
-            "--color=always" => colorize = true,
-            "--color=none" => colorize = false,
-            "--color=auto" => {}
-            "--no-force" | "-n" => force = false,
-            "--simple" | "-s" => simple = true,
-            arg => return Err(format!("unknown command line argument: {}", arg).into()),
+    let cli = Cli::parse_args();
+    process::kill_children_on_interrupt()
+        .map_err(|e| format!("failed to set the signal handler: {}", e))?;
+
+    // 設定ファイルの内容を既定値とし、コマンドライン引数で上書きする
+    let library_root = find_lib_root()?;
+    let config = config::load(&library_root)?;
+
+    let color = cli.color.as_deref().or(config.color.as_deref());
+    let colorize = parse_color(color.unwrap_or("auto"))?;
+    let test_extension = cli
+        .test_extension
+        .or_else(|| config.test_extension.clone())
+        .unwrap_or_else(|| "test".to_string());
+    let layout = Layout::new(
+        &library_root,
+        config.library_extensions.as_deref(),
+        &config.projects,
+        test_extension,
+    )?;
+
+    // サブコマンドを省略した場合は `run` として扱う
+    let command = cli.command.unwrap_or(Command::Run(Box::new(cli.run)));
+    match command {
+        Command::Run(args) => run(*args, config, &library_root, &layout, colorize),
+        Command::List(args) => {
+            let filter = build_filter(&args.filter, &config)?;
+            let format = args.format.or(config.format).unwrap_or(OutputFormat::Text);
+            let tests = enumerate_matching_tests(&library_root, &layout, &filter)?;
+            list(&tests, &library_root, format, colorize)
         }
+        Command::IncludeGraph => {
+            let tests = enumerate_tests(&library_root, &layout)?;
+            let graph = IncludeGraph::build(tests.iter().map(|test| &*test.library))?;
+            print!("{}", graph.to_dot(&library_root));
+            Ok(())
+        }
+        Command::InitTests(args) => {
+            let filter = build_filter(&args, &config)?;
+            let template = config.test_template.map(|dir| library_root.join(dir));
+            let template = scaffold::Template::new(template)?;
+            let tests = enumerate_matching_tests(&library_root, &layout, &filter)?;
+            init_tests(&tests, &template, &library_root)
+        }
+        Command::Coverage(args) => {
+            let filter = build_filter(&args.filter, &config)?;
+            let require_tests = args.require_tests.get().or(config.require_tests);
+            let tests = enumerate_matching_tests(&library_root, &layout, &filter)?;
+            let overall = coverage::print(&coverage::collect(&tests, &library_root), colorize);
+            if require_tests.unwrap_or(false) && !overall.missing.is_empty() {
+                return Err("some libraries have no tests.".into());
+            }
+            Ok(())
+        }
+        Command::CheckHeaders(args) => {
+            let filter = build_filter(&args.filter, &config)?;
+            let tests = enumerate_matching_tests(&library_root, &layout, &filter)?;
+            check_headers(tests, args, config, &library_root, colorize)
+        }
+        Command::Clean => clean(&library_root, &layout, &config),
     }
+}
 
-    let library_root = find_lib_root()?;
-    println!("found library root at {}", library_root.display());
+/// コマンドライン引数と設定ファイルの除外パターンからフィルタを作ります。
+fn build_filter(args: &FilterArgs, config: &Config) -> Result<Filter> {
+    let mut filter = Filter::new(&args.patterns, &args.excludes, args.regex)?;
+    filter.exclude(&config.exclude, false)?;
 
-    let tests = enumerate_tests(&library_root)?;
+    Ok(filter)
+}
 
-    let (mut success, mut failure, mut notfound) = (0, 0, 0);
-    for test in tests {
-        let result = test.judge(force, simple)?;
-        let color = result.get_color();
+/// テストを実行します (`run` サブコマンド)。
+fn run(
+    args: RunArgs,
+    config: Config,
+    library_root: &Path,
+    layout: &Layout,
+    colorize: bool,
+) -> Result<()> {
+    let filter = build_filter(&args.filter, &config)?;
+    let force = args.force().or(config.force).unwrap_or(true);
+    let simple = args.simple().or(config.simple).unwrap_or(false);
+    let jobs = resolve_jobs(args.jobs.or(config.jobs).unwrap_or(1));
+    let timeout = match (args.timeout, config.timeout) {
+        (Some(timeout), _) => Some(timeout),
+        (None, Some(secs)) => Some(secs_to_duration(secs).ok_or("invalid timeout in config")?),
+        (None, None) => None,
+    };
+    let retries = args.retries.or(config.retries).unwrap_or(0);
+    let format = args.format.or(config.format).unwrap_or(OutputFormat::Text);
+    let use_cache = args.cache().or(config.cache).unwrap_or(true);
+    let require_tests = args.require_tests.get().or(config.require_tests);
+    let changed_since = args.changed_since().map(String::from);
+    let mut sanitizers = args.sanitize.or(config.sanitize).unwrap_or_default();
+    sanitizers.sort();
+    sanitizers.dedup();
+    let runner_settings = runner::Settings {
+        kind: args.runner.or(config.runner.kind),
+        command: args.runner_command.or(config.runner.command),
+        compiler: args.compiler.or(config.runner.compiler),
+        compiler_flags: args.compiler_flags.or(config.runner.compiler_flags),
+        standard: None,
+        combination: None,
+        sanitizers: sanitizers.clone(),
+    };
+    let extension_runner_settings: BTreeMap<_, _> = config
+        .runners
+        .into_iter()
+        .map(|(extension, config)| {
+            let settings = runner::Settings {
+                sanitizers: sanitizers.clone(),
+                ..runner::Settings::from(config)
+            };
+            (extension, settings)
+        })
+        .collect();
+
+    match format {
+        OutputFormat::Text => println!("found library root at {}", library_root.display()),
+        OutputFormat::Json => {}
+        OutputFormat::JsonLines => json::print_start_event(library_root)?,
+    }
+
+    let (with_dependents, deps_of) = (&args.with_dependents, &args.deps_of);
+    let mut tests = enumerate_tests(library_root, layout)?;
+    if changed_since.is_some() || !with_dependents.is_empty() || !deps_of.is_empty() {
+        let graph = IncludeGraph::build(tests.iter().map(|test| &*test.library))?;
+        if let Some(rev) = &changed_since {
+            retain_changed(&mut tests, &graph, library_root, rev)?;
+        }
+        if !with_dependents.is_empty() || !deps_of.is_empty() {
+            retain_related(&mut tests, &graph, with_dependents, deps_of)?;
+        }
+    }
+    tests.retain(|test| filter.is_match(&path_root_removed(&test.library, library_root)));
+
+    let runners = Runners::load(&runner_settings, &extension_runner_settings)?;
+    let matrix = Matrix::load(&config.matrix, &runner_settings, &extension_runner_settings)?;
+    let tests = matrix.expand(tests);
 
+    // ランナーの設定が変わったら、ファイルが同じでも結果は変わりうる
+    let salt = format!(
+        "{:?} {:?} {:?} {:?}",
+        runner_settings, extension_runner_settings, config.matrix, layout
+    );
+    let cache = ResultCache::load(library_root, salt, use_cache)?;
+
+    let session = Session {
+        library_root,
+        runners: &runners,
+        matrix: &matrix,
+        cache: &cache,
+        jobs,
+        opts: JudgeOptions {
+            force,
+            simple,
+            capture: jobs > 1
+                || args.junit.is_some()
+                || args.log_dir.is_some()
+                || format != OutputFormat::Text,
+            timeout,
+            retries,
+        },
+        format,
+        junit: args.junit,
+        log_dir: args.log_dir,
+        slowest: args.slowest.unwrap_or(0),
+        colorize,
+    };
+
+    // 実行中に保存されたファイルも変更として扱えるよう、実行する前の状態から監視する
+    let baseline = if args.watch {
+        Some(watch::Snapshot::take(library_root)?)
+    } else {
+        None
+    };
+    let summary = session.run(&tests)?;
+    if let Some(baseline) = baseline {
+        watch_and_rerun(&session, &filter, layout, baseline)?;
+    }
+
+    if summary.has_failure() {
+        Err("some test failed.".into())
+    } else if require_tests.unwrap_or(false) && summary.not_found != 0 {
+        Err("some libraries have no tests.".into())
+    } else {
+        Ok(())
+    }
+}
+
+/// 見つかったテストと、そのテストプロジェクトが存在するかどうかを表示します。
+fn list(tests: &[Test], library_root: &Path, format: OutputFormat, colorize: bool) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            for test in tests {
+                let library = path_root_removed(&test.library, library_root);
+                let project = path_root_removed(&test.project, library_root);
+                if test.project.exists() {
+                    println!("{} -> {}", library, project);
+                } else {
+                    colored_println! {
+                        colorize;
+                        CC::Reset, "{} -> {} ", library, project;
+                        TestResult::NotFound.get_color(), "(missing)";
+                    }
+                }
+            }
+        }
+        OutputFormat::Json | OutputFormat::JsonLines => {
+            let records: Vec<_> = tests
+                .iter()
+                .map(|test| json::ListRecord::new(test, library_root))
+                .collect();
+            if format == OutputFormat::Json {
+                json::print_list(library_root, records)?;
+            } else {
+                json::print_list_lines(&records)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 各ヘッダが単体でコンパイルできるかを確かめます (`check-headers` サブコマンド)。
+fn check_headers(
+    mut tests: Vec<Test>,
+    args: CheckHeadersArgs,
+    config: Config,
+    library_root: &Path,
+    colorize: bool,
+) -> Result<()> {
+    // 検査はテストプロジェクトによらないので、ライブラリごとに一つにまとめる
+    tests.dedup_by(|a, b| a.library == b.library);
+    tests.retain(|test| HeaderCheck::is_header(&test.library));
+    for test in &mut tests {
+        test.variant = None;
+    }
+
+    let simple = args.simple || config.simple.unwrap_or(false);
+    let jobs = resolve_jobs(args.jobs.or(config.jobs).unwrap_or(1));
+    let settings = runner::Settings {
+        kind: None,
+        command: None,
+        compiler: args.compiler.or(config.runner.compiler),
+        compiler_flags: args.compiler_flags.or(config.runner.compiler_flags),
+        standard: None,
+        combination: None,
+        sanitizers: Vec::new(),
+    };
+    let checker = HeaderCheck::new(
+        runner::resolve_compiler(settings.compiler.clone()),
+        runner::compiler_flags(&settings)?,
+    );
+
+    let mut summary = Summary::default();
+    let check = |test: &Test| checker.check(&test.library);
+    judge_all(&tests, jobs, check, |test, judgement| {
+        print_judgement(test, &judgement, library_root, None, simple, colorize)?;
+        summary.add(judgement.result);
+        Ok(())
+    })?;
+
+    colored_println! {
+        colorize;
+        CC::Reset, "check finished. ";
+        CC::Reset, "{} headers, ", summary.total();
+        TestResult::Succeeded.get_color(), "{} ", summary.succeeded;
+        CC::Reset, "self-contained, ";
+        TestResult::NotSelfContained.get_color(), "{} ", summary.not_self_contained;
+        CC::Reset, "not self-contained.";
+    };
+
+    if summary.has_failure() {
+        return Err("some headers are not self-contained.".into());
+    }
+
+    Ok(())
+}
+
+/// テスト結果のキャッシュと、ネイティブランナーが作った実行ファイルを削除します。
+fn clean(library_root: &Path, layout: &Layout, config: &Config) -> Result<()> {
+    let mut targets = vec![cache::file_path(library_root)];
+    let combinations: Vec<_> = std::iter::once(None)
+        .chain(config.matrix.iter().map(|matrix| Some(&*matrix.name)))
+        .collect();
+    for test in enumerate_tests(library_root, layout)? {
+        for combination in &combinations {
+            for &sanitize in &[false, true] {
+                let binary = runner::binary_path(&test.project, *combination, sanitize);
+                targets.push(runner::stamp_path(&binary));
+                targets.push(binary);
+            }
+        }
+    }
+
+    let mut removed = 0;
+    for target in targets.iter().filter(|target| target.is_file()) {
+        fs::remove_file(target)
+            .map_err(|e| format!("failed to remove {}: {}", target.display(), e))?;
+        println!("removed {}", path_root_removed(target, library_root));
+        removed += 1;
+    }
+
+    println!("removed {} files.", removed);
+
+    Ok(())
+}
+
+impl Session<'_> {
+    /// テスト一つの結果を出力します。
+    fn report(&self, test: &Test, judgement: &Judgement) -> Result<()> {
+        let library_root = self.library_root;
+        let log = match &self.log_dir {
+            Some(dir) if judgement.has_run() => {
+                let name = test.name(library_root);
+                let path = logs::write(dir, &name, judgement)
+                    .map_err(|e| format!("failed to write log for {}: {}", name, e))?;
+                Some(path)
+            }
+            _ => None,
+        };
+
+        match self.format {
+            // 組み合わせごとの結果はライブラリごとの表にまとめるので、ここではテストの出力と、
+            // 表からは分からない失敗の理由やログの場所だけを表示する。どの組み合わせのものか
+            // 分かるよう、テストの名前を先に付ける
+            OutputFormat::Text if test.combination.is_some() => {
+                let stderr: &[u8] = if self.opts.simple {
+                    &[]
+                } else {
+                    &judgement.stderr
+                };
+                let lines = detail_lines(judgement, log.as_deref());
+                if !stderr.is_empty() || !lines.is_empty() {
+                    colored_println! {
+                        self.colorize;
+                        judgement.result.get_color(), "{}", test.name(library_root);
+                        CC::Reset, ":";
+                    }
+                    io::stderr().write_all(stderr)?;
+                    for line in lines {
+                        println!("    {}", line);
+                    }
+                }
+            }
+            OutputFormat::Text => print_judgement(
+                test,
+                judgement,
+                library_root,
+                log.as_deref(),
+                self.opts.simple,
+                self.colorize,
+            )?,
+            OutputFormat::Json => {}
+            OutputFormat::JsonLines => {
+                json::print_test_event(json::TestRecord::new(test, judgement, library_root))?
+            }
+        }
+
+        Ok(())
+    }
+
+    /// ライブラリ一つのテストが全て終わったときに、その結果を集計に加えます。テストプロジェクトが
+    /// 複数あった場合は、まとめた結果も表示します。マトリクスを使う場合は、組み合わせごとの結果を
+    /// 表の一行として表示します。
+    fn finish_library(&self, done: &LibraryResult, summary: &mut Summary) {
+        summary.add(done.result);
+        if self.format != OutputFormat::Text {
+            return;
+        }
+
+        let name = path_root_removed(done.library, self.library_root);
+        if !done.combinations.is_empty() {
+            self.matrix
+                .print_row(&name, &done.combinations, self.colorize);
+        } else if done.tests > 1 {
+            colored_println! {
+                self.colorize;
+                CC::Reset, "[";
+                done.result.get_color(), "{}", done.result;
+                CC::Reset, "] {} ", name;
+                CC::DarkGray, "({} projects)", done.tests;
+            }
+        }
+    }
+
+    /// `tests` を実行し、結果を出力します。
+    ///
+    /// 集計はライブラリ単位で行います。テストプロジェクトが複数あるライブラリは、最も悪い結果を
+    /// そのライブラリの結果とします。
+    fn run(&self, tests: &[Test]) -> Result<Summary> {
+        let library_root = self.library_root;
+        let start = Instant::now();
+        let mut finished = Vec::new();
+        let mut summary = Summary::default();
+
+        if self.format == OutputFormat::Text && !self.matrix.is_empty() && !tests.is_empty() {
+            self.matrix.print_header(self.colorize);
+        }
+
+        // 同じライブラリのテストは続けて並んでいるので、そのライブラリのテストが全て終わった
+        // ところで結果をまとめる
+        let mut counts = BTreeMap::new();
+        for test in tests {
+            *counts.entry(&*test.library).or_insert(0) += 1;
+        }
+        let mut current: Option<LibraryResult> = None;
+        let judge = |test: &Test| {
+            let runners = match &test.combination {
+                Some(combination) => self.matrix.runners(combination),
+                None => Some(self.runners),
+            };
+            let runners = runners.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "unknown combination in the matrix")
+            })?;
+            test.judge(runners.get(&test.library), self.cache, &self.opts)
+        };
+        let result = judge_all(tests, self.jobs, judge, |test, judgement| {
+            self.report(test, &judgement)?;
+            let library = current.get_or_insert_with(|| LibraryResult::new(&test.library));
+            library.add(test, judgement.result);
+            if library.tests == counts[&*test.library] {
+                self.finish_library(library, &mut summary);
+                current = None;
+            }
+            finished.push((test, judgement));
+            Ok(())
+        });
+
+        // 途中で失敗しても、それまでに実行したテストの結果はキャッシュに残す。キャッシュは
+        // 実行を速くするためのものなので、書き出せなくても警告するだけにする
+        if let Err(e) = self.cache.save() {
+            eprintln!("warning: {}", e);
+        }
+        result?;
+        let elapsed = start.elapsed();
+
+        match self.format {
+            OutputFormat::Text => {
+                print_summary(&summary, self.colorize);
+                print_slowest(&finished, library_root, self.slowest);
+            }
+            OutputFormat::Json => {
+                let records = finished
+                    .iter()
+                    .map(|(test, judgement)| json::TestRecord::new(test, judgement, library_root))
+                    .collect();
+                json::print_document(
+                    library_root,
+                    records,
+                    json::SummaryRecord::new(&summary, elapsed),
+                )?;
+            }
+            OutputFormat::JsonLines => {
+                json::print_summary_event(json::SummaryRecord::new(&summary, elapsed))?
+            }
+        }
+
+        if let Some(path) = &self.junit {
+            let cases: Vec<_> = finished
+                .iter()
+                .map(|(test, judgement)| junit::Case {
+                    name: test.name(library_root),
+                    judgement,
+                })
+                .collect();
+            junit::write(path, &cases)
+                .map_err(|e| format!("failed to write JUnit report to {}: {}", path, e))?;
+        }
+
+        Ok(summary)
+    }
+}
+
+/// テストプロジェクトが存在しないライブラリについて、雛形からテストプロジェクトを作ります。
+fn init_tests(tests: &[Test], template: &scaffold::Template, library_root: &Path) -> Result<()> {
+    let mut created = 0;
+    for test in tests.iter().filter(|test| !test.project.exists()) {
+        let name = path_root_removed(&test.project, library_root);
+        template
+            .create(test)
+            .map_err(|e| format!("failed to create {}: {}", name, e))?;
+        println!("created {}", name);
+        created += 1;
+    }
+
+    println!("created {} test projects.", created);
+
+    Ok(())
+}
+
+/// ライブラリのルート以下を `baseline` の時点から監視し、変更の影響を受けるテストを実行し直し
+/// 続けます。
+///
+/// テストの実行中に保存されたファイルを見逃さないよう、変更は実行し直す前の時点から監視します。
+/// 実行中にネイティブランナーが作る実行ファイルは監視しないので、それで再実行が繰り返されることは
+/// ありません。
+fn watch_and_rerun(
+    session: &Session,
+    filter: &Filter,
+    layout: &Layout,
+    mut baseline: watch::Snapshot,
+) -> Result<()> {
+    let library_root = session.library_root;
+    if session.format == OutputFormat::Text {
+        println!("watching {} for changes...", library_root.display());
+    }
+
+    loop {
+        let (changed, snapshot) = watch::wait_for_changes(library_root, &baseline)?;
+        baseline = snapshot;
+
+        let rerun = || -> Result<()> {
+            let mut tests = enumerate_matching_tests(library_root, layout, filter)?;
+            let graph = IncludeGraph::build(tests.iter().map(|test| &*test.library))?;
+            let affected = affected_libraries(&tests, &graph, &changed);
+            tests.retain(|test| affected.contains(&deps::normalize(&test.library)));
+            if tests.is_empty() {
+                return Ok(());
+            }
+
+            if session.format == OutputFormat::Text {
+                println!();
+                println!("detected changes, re-running {} tests...", tests.len());
+            }
+            session.run(&session.matrix.expand(tests))?;
+
+            Ok(())
+        };
+
+        // 監視は続けたいので、実行し直すときのエラーは表示するだけにする
+        if let Err(e) = rerun() {
+            eprintln!("error: {}", e);
+        }
+    }
+}
+
+/// テスト一つの結果を表示します。
+fn print_judgement(
+    test: &Test,
+    judgement: &Judgement,
+    library_root: &Path,
+    log: Option<&Path>,
+    simple: bool,
+    colorize: bool,
+) -> io::Result<()> {
+    if !simple {
+        io::stderr().write_all(&judgement.stderr)?;
+    }
+
+    let result = judgement.result;
+    let name = test.name(library_root);
+    if judgement.has_run() {
         colored_println! {
             colorize;
             CC::Reset, "[";
-            color, "{}", result;
-            CC::Reset, "] {}", path_root_removed(&test.library, &library_root);
+            result.get_color(), "{}", result;
+            CC::Reset, "] {} ", name;
+            CC::DarkGray, "({})", format_duration(judgement.duration);
         }
-
-        match result {
-            TestResult::Succeeded => success += 1,
-            TestResult::Failed => failure += 1,
-            TestResult::NotFound => notfound += 1,
+    } else {
+        colored_println! {
+            colorize;
+            CC::Reset, "[";
+            result.get_color(), "{}", result;
+            CC::Reset, "] {}", name;
         }
     }
+
+    for line in detail_lines(judgement, log) {
+        println!("    {}", line);
+    }
+
+    Ok(())
+}
+
+/// 結果の下に字下げして示す、失敗の理由とログの場所を返します。
+fn detail_lines(judgement: &Judgement, log: Option<&Path>) -> Vec<String> {
+    // サニタイザの報告などは、出力を表示しない場合でも失敗の理由として示す
+    let mut lines: Vec<_> = judgement
+        .detail
+        .iter()
+        .flat_map(|detail| detail.lines())
+        .map(str::to_string)
+        .collect();
+
+    // 失敗したテストは、後から詳細を確認できるようログの場所を示す
+    if let (Some(log), TestResult::Failed | TestResult::TimedOut | TestResult::SanitizerError) =
+        (log, judgement.result)
+    {
+        lines.push(format!("log: {}", log.display()));
+    }
+
+    lines
+}
+
+/// 実行に時間のかかったテストを、時間のかかった順に `count` 個まで表示します。
+fn print_slowest(finished: &[(&Test, Judgement)], library_root: &Path, count: usize) {
+    let mut ran: Vec<_> = finished
+        .iter()
+        .filter(|(_, judgement)| judgement.has_run())
+        .collect();
+    if count == 0 || ran.is_empty() {
+        return;
+    }
+
+    ran.sort_by_key(|(_, judgement)| Reverse(judgement.duration));
+    println!("slowest {} tests:", count.min(ran.len()));
+    for (test, judgement) in ran.into_iter().take(count) {
+        println!(
+            "{:>10} {}",
+            format_duration(judgement.duration),
+            test.name(library_root)
+        );
+    }
+}
+
+/// 実行時間を秒単位で表示するための文字列にします。
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
+
+/// 全体の集計結果を表示します。
+fn print_summary(summary: &Summary, colorize: bool) {
     colored_println! {
         colorize;
         CC::Reset, "test finished. ";
-        CC::Reset, "{} total, ", success + failure + notfound;
-        TestResult::NotFound.get_color(), "{} ", notfound;
+        CC::Reset, "{} total, ", summary.total();
+        TestResult::NotFound.get_color(), "{} ", summary.not_found;
         CC::Reset, "skipped, ";
-        TestResult::Succeeded.get_color(), "{} ", success;
+        TestResult::Succeeded.get_color(), "{} ", summary.succeeded;
         CC::Reset, "succeeded, ";
-        TestResult::Failed.get_color(), "{} ", failure;
-        CC::Reset, "failed.";
+        TestResult::Failed.get_color(), "{} ", summary.failed;
+        CC::Reset, "failed, ";
+        TestResult::TimedOut.get_color(), "{} ", summary.timed_out;
+        CC::Reset, "timed out, ";
+        TestResult::SanitizerError.get_color(), "{} ", summary.sanitizer_error;
+        CC::Reset, "sanitizer errors, ";
+        TestResult::Flaky.get_color(), "{} ", summary.flaky;
+        CC::Reset, "flaky, ";
+        TestResult::Cached.get_color(), "{} ", summary.cached;
+        CC::Reset, "cached.";
     };
+}
+
+/// `tests` を最大 `jobs` 個並列に `judge` で判定し、各結果について `report` を呼び出します。
+///
+/// 判定の終わった順ではなく、常に `tests` の並び順で `report` を呼び出します。並列に実行する
+/// 場合は出力が混ざらないよう、`opts.capture` を指定して標準エラー出力を捕捉してください。
+fn judge_all<'a, J, F>(tests: &'a [Test], jobs: usize, judge: J, mut report: F) -> Result<()>
+where
+    J: Fn(&Test) -> io::Result<Judgement> + Sync,
+    F: FnMut(&'a Test, Judgement) -> Result<()>,
+{
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|s| {
+        for _ in 0..jobs.min(tests.len()) {
+            let tx = tx.clone();
+            let next = &next;
+            let judge = &judge;
+            s.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                if idx >= tests.len() {
+                    break;
+                }
+
+                let judgement = judge(&tests[idx]);
+                // 受信側がいなくなった (エラーで中断した) 場合は残りを実行しない
+                if tx.send((idx, judgement)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // 先に終わったテストの結果は、それより前のテストの結果が揃うまで取っておく
+        let mut pending = BTreeMap::new();
+        let mut reported = 0;
+        for (idx, judgement) in rx {
+            pending.insert(idx, judgement);
+            while let Some(judgement) = pending.remove(&reported) {
+                report(&tests[reported], judgement?)?;
+                reported += 1;
+            }
+        }
 
-    if failure != 0 {
-        Err("some test failed.".into())
-    } else {
         Ok(())
+    })
+}
+
+/// `tests` のうち、リビジョン `rev` からの変更の影響を受けるものだけを残します。
+fn retain_changed(
+    tests: &mut Vec<Test>,
+    graph: &IncludeGraph,
+    library_root: &Path,
+    rev: &str,
+) -> Result<()> {
+    let changed = git::changed_files(library_root, rev)?;
+    let affected = affected_libraries(tests, graph, &changed);
+    tests.retain(|test| affected.contains(&deps::normalize(&test.library)));
+
+    Ok(())
+}
+
+/// ファイル `changed` の変更の影響を受けるライブラリを返します。
+///
+/// ライブラリ自身かそのテストプロジェクトの中身が変更されたものに加え、変更されたライブラリを
+/// 直接的または間接的にインクルードしているライブラリも影響を受けるものとします。
+///
+/// テストの実行で作られる実行ファイルは、ソースの変更ではないので無視します。
+fn affected_libraries(
+    tests: &[Test],
+    graph: &IncludeGraph,
+    changed: &[PathBuf],
+) -> BTreeSet<PathBuf> {
+    let changed: Vec<_> = changed
+        .iter()
+        .filter(|path| !runner::is_build_output(path))
+        .map(|path| deps::normalize(path))
+        .collect();
+
+    let mut affected = BTreeSet::new();
+    for test in tests {
+        let library = deps::normalize(&test.library);
+        let project = deps::normalize(&test.project);
+        if changed
+            .iter()
+            .any(|path| *path == library || path.starts_with(&project))
+        {
+            affected.insert(library);
+        }
     }
+
+    // 削除されたライブラリをインクルードしていたライブラリも影響を受けている
+    affected.extend(changed.iter().cloned());
+
+    let dependents = graph.dependents(&affected);
+    affected.extend(dependents);
+
+    affected
+}
+
+/// `tests` のうち、`with_dependents` のライブラリとそれをインクルードしているライブラリ、
+/// および `deps_of` のライブラリがインクルードしているライブラリのテストだけを残します。
+///
+/// ファイルはカレントディレクトリからのパスで指定します。
+fn retain_related(
+    tests: &mut Vec<Test>,
+    graph: &IncludeGraph,
+    with_dependents: &[String],
+    deps_of: &[String],
+) -> Result<()> {
+    let resolve = |files: &[String]| -> Result<BTreeSet<PathBuf>> {
+        let current_dir = env::current_dir()?;
+        files
+            .iter()
+            .map(|file| {
+                let path = deps::normalize(&current_dir.join(file));
+                if graph.contains(&path) {
+                    Ok(path)
+                } else {
+                    Err(format!("{} is not a library under the library root", file).into())
+                }
+            })
+            .collect()
+    };
+
+    let with_dependents = resolve(with_dependents)?;
+    let deps_of = resolve(deps_of)?;
+
+    let mut selected = graph.dependents(&with_dependents);
+    selected.extend(with_dependents);
+    selected.extend(graph.dependencies(&deps_of));
+
+    tests.retain(|test| selected.contains(&deps::normalize(&test.library)));
+
+    Ok(())
 }
 
 /// ライブラリのルートディレクトリかどうか確認します。
 fn check_root(path: &Path) -> bool {
-    path.join("marker_lib_root").exists()
+    path.join(config::MARKER_FILE).exists()
 }
 
 /// ライブラリのルートディレクトリを検索します。
@@ -183,22 +1304,97 @@ fn find_lib_root() -> Result<PathBuf> {
     Err(From::from("failed to find library root."))
 }
 
+/// ライブラリのルート以下のテストのうち、`filter` に一致するものを列挙します。
+fn enumerate_matching_tests(
+    library_root: &Path,
+    layout: &Layout,
+    filter: &Filter,
+) -> Result<Vec<Test>> {
+    let mut tests = enumerate_tests(library_root, layout)?;
+    tests.retain(|test| filter.is_match(&path_root_removed(&test.library, library_root)));
+
+    Ok(tests)
+}
+
 /// `target` 以下のテストファイルを全て列挙します。
-fn enumerate_tests(target: &Path) -> io::Result<Vec<Test>> {
+///
+/// 実行環境によらず同じ順で結果を表示できるよう、パスの順に並べて返します。
+///
+/// テストプロジェクトだけを置くディレクトリや、テストプロジェクトの中は探しません。複数の
+/// ライブラリが同じテストプロジェクトを使うことになる場合はエラーとします。
+fn enumerate_tests(target: &Path, layout: &Layout) -> Result<Vec<Test>> {
+    let tests = enumerate_tests_in(target, layout)?;
+    check_shared_projects(&tests)?;
+
+    Ok(tests)
+}
+
+/// `enumerate_tests` の本体です。`dir` 以下のテストファイルを全て列挙します。
+fn enumerate_tests_in(dir: &Path, layout: &Layout) -> Result<Vec<Test>> {
     let mut result = Vec::new();
-    let entries = fs::read_dir(target)?;
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
 
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
+    // テストプロジェクトの中のソースをライブラリと取り違えないよう、先に同じディレクトリの
+    // ライブラリのテストプロジェクトを求めておく
+    let (libraries, others): (Vec<_>, Vec<_>) =
+        paths.into_iter().partition(|path| layout.is_library(path));
+    let mut cache = DirCache::default();
+    let mut project_dirs = BTreeSet::new();
+    let mut tests = BTreeMap::new();
+    for library in libraries {
+        let library_tests = Test::of_library(library.clone(), layout, &mut cache);
+        project_dirs.extend(
+            library_tests
+                .iter()
+                .map(|test| deps::normalize(&test.project)),
+        );
+        project_dirs.insert(deps::normalize(&layout.container_of(&library)));
+        tests.insert(library, library_tests);
+    }
+    let is_project = |dir: &Path| {
+        layout.is_project_tree(dir)
+            || layout.is_primary_project(dir)
+            || project_dirs.contains(&deps::normalize(dir))
+    };
 
-        if path.is_file() && path.extension().and_then(|x| x.to_str()) == Some("hpp") {
-            result.push(Test::new(path));
-        } else if path.is_dir() {
-            let children = enumerate_tests(&path)?.into_iter();
-            result.extend(children);
+    let mut entries: Vec<_> = others
+        .into_iter()
+        .filter(|path| path.is_dir() && !is_project(path))
+        .chain(tests.keys().cloned())
+        .collect();
+    entries.sort();
+    for path in entries {
+        match tests.remove(&path) {
+            Some(library_tests) => result.extend(library_tests),
+            None => result.extend(enumerate_tests_in(&path, layout)?),
         }
     }
 
     Ok(result)
 }
+
+/// 複数のライブラリが同じテストプロジェクトを使うことになっていないかを確かめます。
+///
+/// `foo.hpp` と `foo.rs` のように拡張子だけが違うライブラリは、規則では同じテストプロジェクトに
+/// なってしまうので、対応表で別のテストプロジェクトを指定する必要があります。
+fn check_shared_projects(tests: &[Test]) -> Result<()> {
+    let mut owners = BTreeMap::new();
+    for test in tests {
+        let project = deps::normalize(&test.project);
+        let owner = owners.entry(project).or_insert(&test.library);
+        if *owner != &test.library {
+            let msg = format!(
+                "{} and {} share the test project {}; map them to separate projects in [projects.map]",
+                owner.display(),
+                test.library.display(),
+                test.project.display()
+            );
+            return Err(msg.into());
+        }
+    }
+
+    Ok(())
+}
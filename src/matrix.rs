@@ -0,0 +1,182 @@
+//! コンパイラや言語標準などの組み合わせ (マトリクス) ごとにテストを実行します。
+//!
+//! 組み合わせはネイティブランナーの設定を上書きするものなので、マトリクスを使うときは既定の
+//! ランナーをネイティブランナーにする必要があります。
+
+use crate::config::MatrixConfig;
+use crate::runner::{RunnerKind, Runners, Settings};
+use crate::{Result, Test, TestResult};
+
+use colored_print::color::ConsoleColor as CC;
+use colored_print::{colored_print, colored_println};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// 組み合わせごとのランナーをまとめた構造体です。
+pub struct Matrix {
+    /// 設定ファイルに書かれた順の組み合わせ
+    combinations: Vec<Combination>,
+}
+
+struct Combination {
+    name: String,
+    runners: Runners,
+}
+
+impl Matrix {
+    /// 設定から組み合わせごとのランナーを用意します。`default` と `by_extension` は組み合わせで
+    /// 上書きする前のランナーの設定です。
+    pub fn load(
+        configs: &[MatrixConfig],
+        default: &Settings,
+        by_extension: &BTreeMap<String, Settings>,
+    ) -> Result<Matrix> {
+        if !configs.is_empty() && default.kind != Some(RunnerKind::Native) {
+            return Err("the matrix requires the native runner".into());
+        }
+
+        let mut names = BTreeSet::new();
+        let combinations = configs
+            .iter()
+            .map(|config| {
+                let name = &config.name;
+                if !is_valid_name(name) {
+                    return Err(format!("invalid combination name: `{}`", name).into());
+                }
+                if !names.insert(name) {
+                    return Err(format!("duplicate combination name: {}", name).into());
+                }
+
+                let by_extension = by_extension
+                    .iter()
+                    .map(|(extension, settings)| (extension.clone(), apply(settings, config)))
+                    .collect();
+                let runners = Runners::load(&apply(default, config), &by_extension)
+                    .map_err(|e| format!("invalid combination {}: {}", name, e))?;
+
+                Ok(Combination {
+                    name: name.clone(),
+                    runners,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Matrix { combinations })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.combinations.is_empty()
+    }
+
+    /// 各テストを組み合わせごとのテストに分けます。マトリクスが空ならそのまま返します。
+    pub fn expand(&self, tests: Vec<Test>) -> Vec<Test> {
+        if self.is_empty() {
+            return tests;
+        }
+
+        let mut expanded = Vec::with_capacity(tests.len() * self.combinations.len());
+        for test in tests {
+            for combination in &self.combinations {
+                expanded.push(Test {
+                    library: test.library.clone(),
+                    project: test.project.clone(),
+                    variant: test.variant.clone(),
+                    combination: Some(combination.name.clone()),
+                });
+            }
+        }
+
+        expanded
+    }
+
+    /// 組み合わせ `name` のランナーを返します。
+    pub fn runners(&self, name: &str) -> Option<&Runners> {
+        self.combinations
+            .iter()
+            .find(|combination| combination.name == name)
+            .map(|combination| &combination.runners)
+    }
+
+    /// 結果の表の見出しとして、組み合わせの名前を並べて表示します。
+    pub fn print_header(&self, colorize: bool) {
+        for combination in &self.combinations {
+            colored_print! {
+                colorize;
+                CC::Reset, "{:<1$}  ", combination.name, cell_width(&combination.name);
+            }
+        }
+        colored_println! {
+            colorize;
+            CC::DarkGray, "library";
+        }
+    }
+
+    /// ライブラリ一つの組み合わせごとの結果 `results` を、表の一行として表示します。
+    pub fn print_row(&self, name: &str, results: &BTreeMap<&str, TestResult>, colorize: bool) {
+        for combination in &self.combinations {
+            let width = cell_width(&combination.name);
+            match results.get(&*combination.name) {
+                Some(result) => colored_print! {
+                    colorize;
+                    result.get_color(), "{:<1$}", result.to_string(), width;
+                    CC::Reset, "  ";
+                },
+                None => print!("{:<1$}  ", "", width),
+            }
+        }
+        println!("{}", name);
+    }
+}
+
+/// 組み合わせ `config` で上書きしたランナーの設定を返します。
+fn apply(settings: &Settings, config: &MatrixConfig) -> Settings {
+    Settings {
+        kind: settings.kind,
+        command: settings.command.clone(),
+        compiler: config
+            .compiler
+            .clone()
+            .or_else(|| settings.compiler.clone()),
+        compiler_flags: config
+            .compiler_flags
+            .clone()
+            .or_else(|| settings.compiler_flags.clone()),
+        standard: config.std.clone().or_else(|| settings.standard.clone()),
+        combination: Some(config.name.clone()),
+        sanitizers: settings.sanitizers.clone(),
+    }
+}
+
+/// 組み合わせの名前として使えるかどうかを返します。名前は実行ファイルの名前にも使うので、
+/// ファイル名に使える文字に限ります。
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || "+-._".contains(ch))
+}
+
+/// 結果の表で組み合わせ `name` の列に取る幅を返します。どの結果も収まるよう、結果の表示の
+/// 最大の長さ以上とします。
+fn cell_width(name: &str) -> usize {
+    name.len().max(TestResult::max_label_len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_fit_every_result_label() {
+        assert_eq!(cell_width("c17"), "SANITIZE".len());
+        assert_eq!(cell_width("gcc-c++17-debug"), "gcc-c++17-debug".len());
+    }
+
+    #[test]
+    fn combination_names_must_be_file_name_safe() {
+        assert!(is_valid_name("gcc-c++17"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("gcc 17"));
+        assert!(!is_valid_name("../x"));
+    }
+}
@@ -0,0 +1,172 @@
+//! 子プロセスの起動・待機・強制終了に関するユーティリティです。
+
+use std::collections::BTreeSet;
+use std::io;
+use std::io::prelude::*;
+use std::process::{self, Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// 子プロセスの終了を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Ctrl-C などで中断されたときの終了コード
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// 新しいプロセスグループで起動し、まだ終了を確認していない子プロセスの ID
+///
+/// これらは端末からの Ctrl-C を受け取らないので、中断されたときに自分で終了させます。
+static PROCESS_GROUPS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// 子プロセスの実行結果を表す構造体です。
+#[derive(Debug)]
+pub struct Finished {
+    /// 終了ステータス。時間切れで強制終了した場合は `None` です。
+    pub status: Option<ExitStatus>,
+
+    /// 捕捉した標準出力 (捕捉しなかった場合は空)
+    pub stdout: Vec<u8>,
+
+    /// 捕捉した標準エラー出力 (捕捉しなかった場合は空)
+    pub stderr: Vec<u8>,
+}
+
+/// `cmd` を起動し、終了するか `timeout` が経過するまで待ちます。
+///
+/// 時間切れになった場合は、子プロセスが起動した孫プロセスも含めて強制終了します。そのため
+/// `timeout` が指定されたときは子プロセスを新しいプロセスグループで起動します。
+pub fn run(cmd: &mut Command, timeout: Option<Duration>) -> io::Result<Finished> {
+    let mut child = match timeout {
+        Some(_) => {
+            // 起動してから登録するまでの間に中断されても取りこぼさないよう、ロックしたまま起動する
+            let mut groups = PROCESS_GROUPS.lock().unwrap();
+            set_new_process_group(cmd);
+            let child = cmd.spawn()?;
+            groups.insert(child.id());
+            child
+        }
+        None => cmd.spawn()?,
+    };
+
+    // パイプが詰まって子プロセスが止まらないよう、捕捉する出力は別スレッドで読み続ける
+    let stdout_reader = child.stdout.take().map(spawn_reader);
+    let stderr_reader = child.stderr.take().map(spawn_reader);
+
+    let status = match timeout {
+        Some(timeout) => {
+            let status = wait_timeout(&mut child, timeout);
+            PROCESS_GROUPS.lock().unwrap().remove(&child.id());
+            status?
+        }
+        None => Some(child.wait()?),
+    };
+
+    Ok(Finished {
+        status,
+        stdout: join_reader(stdout_reader)?,
+        stderr: join_reader(stderr_reader)?,
+    })
+}
+
+/// Ctrl-C や SIGTERM で中断されたときに、新しいプロセスグループで起動した子プロセスも含めて
+/// 終了するようにします。プログラムの開始時に一度だけ呼び出してください。
+pub fn kill_children_on_interrupt() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        // ロックしたままにして、これ以上子プロセスを起動させない
+        let groups = PROCESS_GROUPS.lock().unwrap_or_else(|e| e.into_inner());
+        for &id in groups.iter() {
+            kill_tree_of(id);
+        }
+        process::exit(INTERRUPTED_EXIT_CODE);
+    })
+}
+
+/// `pipe` を最後まで読み込むスレッドを起動します。
+fn spawn_reader<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        pipe.read_to_end(&mut buf).map(|_| buf)
+    })
+}
+
+/// `spawn_reader` で起動したスレッドの読み込んだ内容を受け取ります。
+fn join_reader(reader: Option<JoinHandle<io::Result<Vec<u8>>>>) -> io::Result<Vec<u8>> {
+    match reader {
+        Some(reader) => reader
+            .join()
+            .map_err(|_| io::Error::other("pipe reader panicked"))?,
+        None => Ok(Vec::new()),
+    }
+}
+
+/// `child` の終了を最大 `timeout` だけ待ちます。時間切れの場合はプロセスツリーごと終了させ
+/// `None` を返します。
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if Instant::now() >= deadline {
+            kill_tree(child)?;
+            child.wait()?;
+            return Ok(None);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(unix)]
+fn set_new_process_group(cmd: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn set_new_process_group(_cmd: &mut Command) {}
+
+/// `child` とその子孫のプロセスを強制終了します。
+fn kill_tree(child: &mut Child) -> io::Result<()> {
+    if kill_tree_of(child.id()) {
+        Ok(())
+    } else {
+        child.kill()
+    }
+}
+
+/// ID が `id` のプロセスとその子孫を強制終了し、できたかどうかを返します。
+#[cfg(unix)]
+fn kill_tree_of(id: u32) -> bool {
+    // 子プロセスはプロセスグループのリーダーなので、グループ全体にシグナルを送る
+    Command::new("kill")
+        .arg("-KILL")
+        .arg("--")
+        .arg(format!("-{}", id))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// ID が `id` のプロセスとその子孫を強制終了し、できたかどうかを返します。
+#[cfg(windows)]
+fn kill_tree_of(id: u32) -> bool {
+    Command::new("taskkill")
+        .args(&["/F", "/T", "/PID"])
+        .arg(id.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// ID が `id` のプロセスとその子孫を強制終了し、できたかどうかを返します。
+#[cfg(not(any(unix, windows)))]
+fn kill_tree_of(_id: u32) -> bool {
+    false
+}
@@ -0,0 +1,312 @@
+//! テストプロジェクトを実際に実行する方法 (ランナー) を扱います。
+
+mod native;
+
+pub use self::native::{
+    binary_path, is_build_output, resolve_compiler, stamp_path, Native, Sanitizer, SOURCE_FILE,
+};
+
+use crate::process;
+use crate::{JudgeOptions, Judgement, Result, Test, TestResult};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+/// 使うランナーの種類を表す列挙体です。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunnerKind {
+    ProconAssistant,
+    Native,
+    Custom,
+}
+
+/// ランナーの選択と設定を表す構造体です。
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// 使うランナー。`None` なら `command` があれば `Custom` を、なければ `ProconAssistant` を
+    /// 使います。
+    pub kind: Option<RunnerKind>,
+
+    /// カスタムランナーで実行するコマンド
+    pub command: Option<String>,
+
+    /// ネイティブランナーで使うコンパイラ
+    pub compiler: Option<String>,
+
+    /// ネイティブランナーで使うコンパイルオプション (空白区切り)
+    pub compiler_flags: Option<String>,
+
+    /// ネイティブランナーで使う言語標準 (`c++17` など)。コンパイルオプションの `-std=` を
+    /// 置き換えます。
+    pub standard: Option<String>,
+
+    /// マトリクスの組み合わせの名前。ネイティブランナーは実行ファイルを組み合わせごとに分けます。
+    pub combination: Option<String>,
+
+    /// ネイティブランナーでビルドに組み込むサニタイザ
+    pub sanitizers: Vec<Sanitizer>,
+}
+
+/// テストプロジェクトを実行する方法を表すトレイトです。
+///
+/// テストは並列に実行されることがあるので、複数のスレッドから同時に呼び出せる必要があります。
+pub trait Runner: Sync {
+    /// `test` のテストプロジェクトを実行して結果を返します。プロジェクトが存在することは
+    /// 呼び出し側で確認済みです。
+    fn run(&self, test: &Test, opts: &JudgeOptions) -> io::Result<Judgement>;
+}
+
+/// ライブラリの拡張子ごとに使うランナーをまとめた構造体です。
+pub struct Runners {
+    /// 拡張子ごとの設定がないライブラリに使うランナー
+    default: Box<dyn Runner>,
+
+    by_extension: BTreeMap<String, Box<dyn Runner>>,
+}
+
+/// procon-assistant でテストプロジェクトを実行するランナーです。
+#[derive(Debug)]
+pub struct ProconAssistant;
+
+/// 設定で指定されたコマンドでテストプロジェクトを実行するランナーです。
+///
+/// コマンド中の `{project}` と `{library}` は、それぞれテストプロジェクトのディレクトリと
+/// ライブラリのパスに置き換えます。コマンドはシェルを介さずに、テストプロジェクトを
+/// カレントディレクトリとして実行します。
+#[derive(Debug)]
+pub struct CustomCommand {
+    /// 空白で区切ったコマンドの各単語
+    words: Vec<String>,
+}
+
+impl Runner for ProconAssistant {
+    fn run(&self, test: &Test, opts: &JudgeOptions) -> io::Result<Judgement> {
+        let mut cmd = Command::new("procon-assistant");
+        cmd.arg("--quiet");
+
+        cmd.arg("run");
+
+        if opts.force {
+            cmd.arg("--force");
+        }
+
+        run_command(&mut cmd, test, opts)
+    }
+}
+
+impl CustomCommand {
+    /// コマンドの文字列を解釈します。
+    ///
+    /// 空白を含む単語は `"` か `'` で囲んで指定できます。
+    pub fn parse(command: &str) -> Result<CustomCommand> {
+        let words = split_words(command)?;
+        if words.is_empty() {
+            return Err("runner command is empty".into());
+        }
+
+        Ok(CustomCommand { words })
+    }
+}
+
+impl Runner for CustomCommand {
+    fn run(&self, test: &Test, opts: &JudgeOptions) -> io::Result<Judgement> {
+        let expand = |word: &str| {
+            word.replace("{project}", &test.project.display().to_string())
+                .replace("{library}", &test.library.display().to_string())
+        };
+
+        let mut cmd = Command::new(expand(&self.words[0]));
+        cmd.args(self.words[1..].iter().map(|word| expand(word)));
+
+        run_command(&mut cmd, test, opts)
+    }
+}
+
+impl Runners {
+    /// 既定の設定 `default` と拡張子ごとの設定 `by_extension` からランナーを用意します。
+    pub fn load(default: &Settings, by_extension: &BTreeMap<String, Settings>) -> Result<Runners> {
+        let by_extension = by_extension
+            .iter()
+            .map(|(extension, settings)| {
+                let runner = load(settings)
+                    .map_err(|e| format!("invalid runner for .{} libraries: {}", extension, e))?;
+                Ok((extension.trim_start_matches('.').to_string(), runner))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Runners {
+            default: load(default)?,
+            by_extension,
+        })
+    }
+
+    /// ライブラリ `library` のテストに使うランナーを返します。
+    pub fn get(&self, library: &Path) -> &dyn Runner {
+        library
+            .extension()
+            .and_then(|extension| self.by_extension.get(&*extension.to_string_lossy()))
+            .unwrap_or(&self.default)
+            .as_ref()
+    }
+}
+
+/// 設定に従ってランナーを用意します。
+///
+/// サニタイザはネイティブランナーでしか使えないので、他のランナーに設定されていればエラーとします。
+pub fn load(settings: &Settings) -> Result<Box<dyn Runner>> {
+    let kind = settings.kind.unwrap_or(if settings.command.is_some() {
+        RunnerKind::Custom
+    } else {
+        RunnerKind::ProconAssistant
+    });
+    if !settings.sanitizers.is_empty() && kind != RunnerKind::Native {
+        return Err("sanitizers require the native runner".into());
+    }
+
+    match kind {
+        RunnerKind::ProconAssistant => Ok(Box::new(ProconAssistant)),
+        RunnerKind::Native => {
+            let flags = compiler_flags(settings)?;
+            let compiler = settings.compiler.clone();
+            let combination = settings.combination.clone();
+            let sanitize = !settings.sanitizers.is_empty();
+            Ok(Box::new(Native::new(
+                compiler,
+                flags,
+                combination,
+                sanitize,
+            )))
+        }
+        RunnerKind::Custom => {
+            let command = settings
+                .command
+                .as_ref()
+                .ok_or("the custom runner requires a command")?;
+            let runner = CustomCommand::parse(command)
+                .map_err(|e| format!("invalid runner command: {}", e))?;
+            Ok(Box::new(runner))
+        }
+    }
+}
+
+/// 設定されたコンパイルオプションを単語に分割します。設定がなければ既定のオプションを返します。
+///
+/// 言語標準が設定されていれば、オプション中の `-std=` を取り除いてその標準を指定します。
+/// サニタイザが設定されていれば、それを組み込むオプションを加えます。
+pub fn compiler_flags(settings: &Settings) -> Result<Vec<String>> {
+    let mut flags = match &settings.compiler_flags {
+        Some(flags) => split_words(flags)?,
+        None => native::DEFAULT_FLAGS
+            .iter()
+            .map(|&flag| flag.into())
+            .collect(),
+    };
+
+    if let Some(standard) = &settings.standard {
+        flags.retain(|flag| !flag.starts_with("-std="));
+        flags.push(format!("-std={}", standard));
+    }
+    flags.extend(native::sanitizer_flags(&settings.sanitizers));
+
+    Ok(flags)
+}
+
+/// テストプロジェクトをカレントディレクトリとして `cmd` を実行し、その結果を返します。
+///
+/// 出力の捕捉や制限時間など、`opts` の指定のうちランナーによらない部分はここで扱います。
+pub fn run_command(cmd: &mut Command, test: &Test, opts: &JudgeOptions) -> io::Result<Judgement> {
+    cmd.current_dir(&test.project).stdin(Stdio::null());
+
+    if opts.capture {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    } else if opts.simple {
+        cmd.stdout(Stdio::null()).stderr(Stdio::null());
+    } else {
+        cmd.stdout(Stdio::null()).stderr(Stdio::inherit());
+    }
+
+    let start = Instant::now();
+    let finished = process::run(cmd, opts.timeout)?;
+    let duration = start.elapsed();
+    let result = match finished.status {
+        Some(status) if status.success() => TestResult::Succeeded,
+        Some(_) => TestResult::Failed,
+        None => TestResult::TimedOut,
+    };
+
+    Ok(Judgement {
+        result,
+        exit_code: finished.status.and_then(|status| status.code()),
+        duration,
+        stdout: finished.stdout,
+        stderr: finished.stderr,
+        detail: None,
+    })
+}
+
+/// コマンドの文字列を空白で単語に分割します。引用符で囲まれた部分は一つの単語の一部とします。
+fn split_words(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+
+    for ch in command.chars() {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some(_) => word.get_or_insert_with(String::new).push(ch),
+            None if ch == '"' || ch == '\'' => {
+                quote = Some(ch);
+                word.get_or_insert_with(String::new);
+            }
+            None if ch.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(format!("unterminated quote in `{}`", command).into());
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<String> {
+        split_words(command).unwrap()
+    }
+
+    #[test]
+    fn split_words_on_whitespace() {
+        assert_eq!(words("  g++ -O2\t-o main "), ["g++", "-O2", "-o", "main"]);
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn split_words_keeps_quoted_parts_together() {
+        assert_eq!(
+            words(r#"sh -c "echo {project}""#),
+            ["sh", "-c", "echo {project}"]
+        );
+        assert_eq!(words(r#"a'b c'd "it's""#), ["ab cd", "it's"]);
+    }
+
+    #[test]
+    fn split_words_keeps_empty_quoted_words() {
+        assert_eq!(words(r#"cmd "" ''"#), ["cmd", "", ""]);
+    }
+
+    #[test]
+    fn split_words_rejects_unterminated_quotes() {
+        assert!(split_words(r#"echo "hello"#).is_err());
+    }
+}
@@ -0,0 +1,469 @@
+//! procon-assistant を使わず、自前でコンパイルしてサンプルケースを確かめるランナーです。
+
+use super::Runner;
+use crate::deps;
+use crate::process;
+use crate::{JudgeOptions, Judgement, Test, TestResult};
+
+use serde::Deserialize;
+
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+
+/// テストプロジェクトのソースファイル
+pub const SOURCE_FILE: &str = "main.cpp";
+
+/// 実行ファイルをビルドしたときのコンパイラとオプションを記録するファイルの拡張子
+const STAMP_EXTENSION: &str = ".stamp";
+
+/// コンパイラが指定されなかった場合に使うコンパイラ
+const DEFAULT_COMPILER: &str = "g++";
+
+/// コンパイルオプションが指定されなかった場合に使うオプション
+pub const DEFAULT_FLAGS: &[&str] = &["-std=c++17", "-O2"];
+
+/// サニタイザを使うときにコンパイルオプションに加えるオプション。最初の問題で止め、報告に
+/// 行番号が出るようにします。
+const SANITIZER_FLAGS: &[&str] = &["-fno-sanitize-recover=all", "-fno-omit-frame-pointer", "-g"];
+
+/// サニタイザの報告の始まりを表す文字列
+const SANITIZER_MARKERS: &[&str] = &[
+    "ERROR: AddressSanitizer",
+    "ERROR: LeakSanitizer",
+    ": runtime error: ",
+];
+
+/// サンプルケースが無いときに、入力なしで実行した結果の表示に使う名前
+const NO_CASES_NAME: &str = "(no cases)";
+
+/// 結果に含めるサニタイザの報告の最大の行数
+const REPORT_EXCERPT_LINES: usize = 10;
+
+/// ネイティブランナーでビルドに組み込むサニタイザの種類です。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+}
+
+/// テストプロジェクトの `main.cpp` をコンパイルし、`*.in` を入力として実行した結果を
+/// 同名の `*.out` と比較するランナーです。ケースが一つも無ければ入力なしで一度だけ実行し、
+/// 終了ステータスだけを確かめます。
+#[derive(Debug)]
+pub struct Native {
+    compiler: String,
+    flags: Vec<String>,
+
+    /// マトリクスの組み合わせの名前 (実行ファイルの名前に使います)
+    combination: Option<String>,
+
+    /// サニタイザを組み込んでビルドするかどうか (実行ファイルの名前に使います)
+    sanitize: bool,
+}
+
+/// ケース一つの判定結果を表す列挙体です。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Accepted,
+    WrongAnswer,
+    RuntimeError,
+    TimeLimitExceeded,
+
+    /// サニタイザが問題を報告した
+    SanitizerError,
+}
+
+impl Native {
+    /// `compiler` が `None` なら環境変数 `CXX` のコンパイラ (なければ `g++`) を使います。
+    pub fn new(
+        compiler: Option<String>,
+        flags: Vec<String>,
+        combination: Option<String>,
+        sanitize: bool,
+    ) -> Native {
+        Native {
+            compiler: resolve_compiler(compiler),
+            flags,
+            combination,
+            sanitize,
+        }
+    }
+
+    /// 実行ファイルが古くなっていればコンパイルし、実行ファイルが使えるかどうかを返します。
+    ///
+    /// `main.cpp` とライブラリ、およびそれらが (間接的に) インクルードしているファイルのどれかが
+    /// 実行ファイルより新しいか、コンパイラやオプションが前回のビルドと違えば古いものとします。
+    ///
+    /// コンパイルに失敗した場合はコンパイラの出力を `log` に書き込み `Some(false)` を、
+    /// 制限時間を過ぎた場合は `None` を返します。
+    fn compile(
+        &self,
+        test: &Test,
+        binary: &Path,
+        force: bool,
+        deadline: Option<Instant>,
+        log: &mut Vec<u8>,
+    ) -> io::Result<Option<bool>> {
+        let source = test.project.join(SOURCE_FILE);
+        let stamp = stamp_path(binary);
+        let settings = self.stamp();
+        if !force {
+            let sources = deps::with_includes([&*source, &*test.library])?;
+            let same_settings = fs::read_to_string(&stamp).is_ok_and(|built| built == settings);
+            if same_settings && is_up_to_date(binary, &sources) {
+                return Ok(Some(true));
+            }
+        }
+
+        let mut cmd = Command::new(&self.compiler);
+        cmd.args(&self.flags)
+            .arg("-o")
+            .arg(binary)
+            .arg(&source)
+            .current_dir(&test.project)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let finished = process::run(&mut cmd, remaining(deadline)).map_err(|e| {
+            let msg = format!("failed to run compiler `{}`: {}", self.compiler, e);
+            io::Error::new(e.kind(), msg)
+        })?;
+        match finished.status {
+            Some(status) if status.success() => {
+                fs::write(&stamp, settings)?;
+                Ok(Some(true))
+            }
+            Some(_) => {
+                writeln!(log, "[CE] failed to compile {}", SOURCE_FILE)?;
+                log.extend(finished.stdout);
+                log.extend(finished.stderr);
+                Ok(Some(false))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 実行ファイルのスタンプに記録する、ビルドに使うコンパイラとオプションを返します。
+    fn stamp(&self) -> String {
+        let mut stamp = self.compiler.clone();
+        for flag in &self.flags {
+            stamp.push('\n');
+            stamp.push_str(flag);
+        }
+
+        stamp
+    }
+
+    /// サンプルケース `case` の入力を標準入力として実行ファイルを実行し、結果を期待する出力と
+    /// 比較します。`case` が `None` なら入力なしで実行し、終了ステータスだけを確かめます。
+    ///
+    /// サニタイザが問題を報告した場合は、`report` が空ならその抜粋を書き込みます。
+    fn run_case(
+        &self,
+        test: &Test,
+        binary: &Path,
+        case: Option<&(PathBuf, PathBuf)>,
+        deadline: Option<Instant>,
+        log: &mut Vec<u8>,
+        report: &mut Option<String>,
+    ) -> io::Result<Verdict> {
+        let (stdin, expected) = match case {
+            Some((input, output)) => (
+                Stdio::from(File::open(input)?),
+                Some(fs::read_to_string(output)?),
+            ),
+            None => (Stdio::null(), None),
+        };
+
+        let mut cmd = Command::new(binary);
+        cmd.current_dir(&test.project)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let finished = process::run(&mut cmd, remaining(deadline))?;
+        let verdict = match finished.status {
+            None => Verdict::TimeLimitExceeded,
+            Some(status) if !status.success() => match sanitizer_report(&finished.stderr) {
+                Some(excerpt) => {
+                    report.get_or_insert(excerpt);
+                    Verdict::SanitizerError
+                }
+                None => Verdict::RuntimeError,
+            },
+            Some(_) => match &expected {
+                Some(expected)
+                    if !same_output(&String::from_utf8_lossy(&finished.stdout), expected) =>
+                {
+                    Verdict::WrongAnswer
+                }
+                _ => Verdict::Accepted,
+            },
+        };
+
+        let name = match case {
+            Some((input, _)) => input.file_name().unwrap_or_default().to_string_lossy(),
+            None => NO_CASES_NAME.into(),
+        };
+        writeln!(log, "[{}] {}", verdict.abbr(), name)?;
+        match verdict {
+            Verdict::WrongAnswer => {
+                writeln!(log, "expected:")?;
+                log.extend(expected.unwrap_or_default().as_bytes());
+                writeln!(log, "actual:")?;
+                log.extend(&finished.stdout);
+            }
+            Verdict::RuntimeError | Verdict::SanitizerError => log.extend(&finished.stderr),
+            Verdict::Accepted | Verdict::TimeLimitExceeded => {}
+        }
+
+        Ok(verdict)
+    }
+}
+
+impl Runner for Native {
+    fn run(&self, test: &Test, opts: &JudgeOptions) -> io::Result<Judgement> {
+        let start = Instant::now();
+        let deadline = opts.timeout.map(|timeout| start + timeout);
+        let binary = binary_path(&test.project, self.combination.as_deref(), self.sanitize);
+
+        // ケースごとの判定結果は、テストの標準エラー出力として報告する
+        let mut log = Vec::new();
+        let mut report = None;
+        let result = match self.compile(test, &binary, opts.force, deadline, &mut log)? {
+            None => TestResult::TimedOut,
+            Some(false) => TestResult::Failed,
+            Some(true) => {
+                // サンプルケースが無ければ、入力なしで一度だけ実行して終了ステータスを確かめる
+                let cases = enumerate_cases(&test.project)?;
+                let cases = if cases.is_empty() {
+                    vec![None]
+                } else {
+                    cases.iter().map(Some).collect()
+                };
+
+                let mut result = TestResult::Succeeded;
+                for case in cases {
+                    let verdict =
+                        self.run_case(test, &binary, case, deadline, &mut log, &mut report)?;
+                    // 前のケースのより悪い結果になったときだけ置き換える
+                    let case_result = verdict.result();
+                    if case_result.severity() > result.severity() {
+                        result = case_result;
+                    }
+
+                    // 制限時間はテスト全体に対するものなので、残りのケースは実行しない
+                    if verdict == Verdict::TimeLimitExceeded {
+                        break;
+                    }
+                }
+
+                result
+            }
+        };
+
+        Ok(Judgement {
+            result,
+            exit_code: None,
+            duration: start.elapsed(),
+            stdout: Vec::new(),
+            stderr: log,
+            detail: report,
+        })
+    }
+}
+
+impl Verdict {
+    /// このケースだけを見たときのテストの結果を返します。
+    fn result(self) -> TestResult {
+        match self {
+            Verdict::Accepted => TestResult::Succeeded,
+            Verdict::WrongAnswer | Verdict::RuntimeError => TestResult::Failed,
+            Verdict::TimeLimitExceeded => TestResult::TimedOut,
+            Verdict::SanitizerError => TestResult::SanitizerError,
+        }
+    }
+
+    fn abbr(self) -> &'static str {
+        match self {
+            Verdict::Accepted => "AC",
+            Verdict::WrongAnswer => "WA",
+            Verdict::RuntimeError => "RE",
+            Verdict::TimeLimitExceeded => "TLE",
+            Verdict::SanitizerError => "SAN",
+        }
+    }
+}
+
+impl Sanitizer {
+    /// `-fsanitize=` に指定する名前を返します。
+    pub fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+        }
+    }
+}
+
+/// `sanitizers` を組み込んでビルドするためのコンパイルオプションを返します。
+pub fn sanitizer_flags(sanitizers: &[Sanitizer]) -> Vec<String> {
+    if sanitizers.is_empty() {
+        return Vec::new();
+    }
+
+    let names: Vec<_> = sanitizers
+        .iter()
+        .map(|sanitizer| sanitizer.name())
+        .collect();
+    let mut flags = vec![format!("-fsanitize={}", names.join(","))];
+    flags.extend(SANITIZER_FLAGS.iter().map(|&flag| flag.to_string()));
+
+    flags
+}
+
+/// 標準エラー出力 `stderr` からサニタイザの最初の報告を探し、その抜粋を返します。
+fn sanitizer_report(stderr: &[u8]) -> Option<String> {
+    let stderr = String::from_utf8_lossy(stderr);
+    let lines: Vec<_> = stderr.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| SANITIZER_MARKERS.iter().any(|marker| line.contains(marker)))?;
+
+    let end = lines.len().min(start + REPORT_EXCERPT_LINES);
+    Some(lines[start..end].join("\n"))
+}
+
+/// 使うコンパイラを決めます。`compiler` が `None` なら環境変数 `CXX` のコンパイラ
+/// (なければ `g++`) とします。
+pub fn resolve_compiler(compiler: Option<String>) -> String {
+    compiler
+        .or_else(|| env::var("CXX").ok().filter(|cxx| !cxx.is_empty()))
+        .unwrap_or_else(|| DEFAULT_COMPILER.to_string())
+}
+
+/// テストプロジェクト `project` をコンパイルしてできる実行ファイルのパスを返します。
+///
+/// マトリクスの組み合わせ `combination` ごとに、`main-<組み合わせ>` のように別の実行ファイルと
+/// します。サニタイザを組み込む場合はさらに `-sanitize` を付けます。
+pub fn binary_path(project: &Path, combination: Option<&str>, sanitize: bool) -> PathBuf {
+    let mut name = match combination {
+        Some(combination) => format!("main-{}", combination),
+        None => "main".to_string(),
+    };
+    if sanitize {
+        name.push_str("-sanitize");
+    }
+    project.join(format!("{}{}", name, env::consts::EXE_SUFFIX))
+}
+
+/// 実行ファイル `binary` をビルドしたときのコンパイラとオプションを記録するファイルのパスを
+/// 返します。
+pub fn stamp_path(binary: &Path) -> PathBuf {
+    let mut path = OsString::from(binary);
+    path.push(STAMP_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// `path` がネイティブランナーの作ったファイル (実行ファイルとそのスタンプ) かどうかを、名前から
+/// 判断します。
+pub fn is_build_output(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    let name = name.strip_suffix(STAMP_EXTENSION).unwrap_or(name);
+    match name.strip_suffix(env::consts::EXE_SUFFIX) {
+        Some(stem) => stem == "main" || stem.starts_with("main-"),
+        None => false,
+    }
+}
+
+/// テストプロジェクトにある `*.in` と、それに対応する `*.out` の組を名前順に列挙します。
+fn enumerate_cases(project: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut cases = Vec::new();
+    for entry in fs::read_dir(project)? {
+        let input = entry?.path();
+        if input.extension().and_then(|x| x.to_str()) != Some("in") {
+            continue;
+        }
+
+        let output = input.with_extension("out");
+        if output.exists() {
+            cases.push((input, output));
+        }
+    }
+    cases.sort();
+
+    Ok(cases)
+}
+
+/// 行末の空白と末尾の空行を無視して、出力が一致するかどうかを返します。
+fn same_output(actual: &str, expected: &str) -> bool {
+    fn lines(s: &str) -> Vec<&str> {
+        let mut lines: Vec<_> = s.lines().map(str::trim_end).collect();
+        while lines.last() == Some(&"") {
+            lines.pop();
+        }
+        lines
+    }
+
+    lines(actual) == lines(expected)
+}
+
+/// `binary` が `sources` のどれよりも新しいかどうかを返します。
+fn is_up_to_date(binary: &Path, sources: &BTreeSet<PathBuf>) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified());
+    let built = match modified(binary) {
+        Ok(built) => built,
+        Err(_) => return false,
+    };
+
+    sources
+        .iter()
+        .all(|source| modified(source).unwrap_or(SystemTime::UNIX_EPOCH) <= built)
+}
+
+/// 期限までの残り時間を返します。
+fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_output_ignores_trailing_whitespace_and_blank_lines() {
+        assert!(same_output("1 2 \n3\n\n\n", "1 2\n3"));
+        assert!(same_output("1\r\n2\r\n", "1\n2\n"));
+        assert!(same_output("", "\n\n"));
+    }
+
+    #[test]
+    fn same_output_respects_leading_whitespace_and_inner_blank_lines() {
+        assert!(!same_output(" 1\n", "1\n"));
+        assert!(!same_output("1\n\n2\n", "1\n2\n"));
+        assert!(!same_output("1\n", "1\n2\n"));
+    }
+
+    #[test]
+    fn sanitizer_report_starts_at_the_first_report() {
+        let stderr = b"debug\n==1==ERROR: AddressSanitizer: heap-buffer-overflow\nREAD of size 4\n";
+        let report = sanitizer_report(stderr).unwrap();
+        assert_eq!(
+            report,
+            "==1==ERROR: AddressSanitizer: heap-buffer-overflow\nREAD of size 4"
+        );
+        assert!(sanitizer_report(b"terminate called after throwing\n").is_none());
+    }
+}
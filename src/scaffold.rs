@@ -0,0 +1,133 @@
+//! 存在しないテストプロジェクトを雛形から作ります。
+
+use crate::deps;
+use crate::runner::SOURCE_FILE;
+use crate::{Result, Test};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 雛形に `main.cpp` がない場合に作る `main.cpp` の内容
+const DEFAULT_SOURCE: &str = "#include \"{library}\"\n\nint main() {}\n";
+
+/// `main.cpp` からインクルードできる、C や C++ のライブラリの拡張子
+const CPP_EXTENSIONS: &[&str] = &["hpp", "h", "hh", "hxx", "cpp", "cc", "cxx"];
+
+/// テストプロジェクトの雛形です。
+///
+/// 雛形のディレクトリの中身をそのままテストプロジェクトにコピーします。その際、テキストファイル
+/// 中の `{library}` はテストプロジェクトからライブラリへの相対パスに置き換えます。
+#[derive(Debug)]
+pub struct Template {
+    /// 雛形のディレクトリ (`None` なら `main.cpp` だけを作ります)
+    dir: Option<PathBuf>,
+}
+
+impl Template {
+    pub fn new(dir: Option<PathBuf>) -> Result<Template> {
+        if let Some(dir) = &dir {
+            if !dir.is_dir() {
+                return Err(format!("test template {} is not a directory", dir.display()).into());
+            }
+        }
+
+        Ok(Template { dir })
+    }
+
+    /// `test` のテストプロジェクトを作ります。
+    ///
+    /// C や C++ のライブラリで雛形に `main.cpp` がなければ、ライブラリをインクルードするだけの
+    /// `main.cpp` を作ります。
+    pub fn create(&self, test: &Test) -> io::Result<()> {
+        let include = relative_path(&test.project, &test.library);
+        fs::create_dir_all(&test.project)?;
+        if let Some(dir) = &self.dir {
+            copy_dir(dir, &test.project, &include)?;
+        }
+
+        // 他の言語のライブラリでは、何を作ればよいか分からないので雛形のコピーだけにする
+        let is_cpp = test
+            .library
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| CPP_EXTENSIONS.contains(&extension));
+        let source = test.project.join(SOURCE_FILE);
+        if is_cpp && !source.exists() {
+            fs::write(source, DEFAULT_SOURCE.replace("{library}", &include))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `from` の中身を `to` にコピーし、テキストファイル中の `{library}` を `include` に置き換えます。
+fn copy_dir(from: &Path, to: &Path, include: &str) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let src = entry.path();
+        let dst = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&src, &dst, include)?;
+            continue;
+        }
+
+        let content = fs::read(&src)?;
+        match String::from_utf8(content) {
+            Ok(text) => fs::write(&dst, text.replace("{library}", include))?,
+            Err(e) => fs::write(&dst, e.into_bytes())?,
+        }
+    }
+
+    Ok(())
+}
+
+/// ディレクトリ `from` から `to` への相対パスを `/` 区切りで返します。
+fn relative_path(from: &Path, to: &Path) -> String {
+    let from = deps::normalize(from);
+    let to = deps::normalize(to);
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let ups = from.components().skip(common).map(|_| "..".to_string());
+    let downs = to
+        .components()
+        .skip(common)
+        .map(|component| component.as_os_str().to_string_lossy().into_owned());
+
+    ups.chain(downs).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_path_into_subdirectory() {
+        let path = relative_path(Path::new("/lib"), Path::new("/lib/graph/lca.hpp"));
+        assert_eq!(path, "graph/lca.hpp");
+    }
+
+    #[test]
+    fn relative_path_to_parent_directories() {
+        let from = Path::new("/lib/tests/graph/lca");
+        assert_eq!(
+            relative_path(from, Path::new("/lib/graph/lca.hpp")),
+            "../../../graph/lca.hpp"
+        );
+        assert_eq!(relative_path(from, Path::new("/lib/tests/graph")), "..");
+    }
+
+    #[test]
+    fn relative_path_normalizes_dots() {
+        let from = Path::new("/lib/./graph/../ds/seg.test");
+        assert_eq!(
+            relative_path(from, Path::new("/lib/ds/seg.hpp")),
+            "../seg.hpp"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! ライブラリのルート以下のファイルの変更を監視します。
+
+use crate::runner;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// ファイルの変更を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 変更を見つけてから、続けて行われる変更をまとめるために待つ時間
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// ある時点でのファイルの更新日時の一覧です。
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    files: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl Snapshot {
+    /// `root` 以下のファイルの更新日時を記録します。
+    ///
+    /// `.git` など `.` で始まるディレクトリと、テストの実行中に作られるネイティブランナーの
+    /// 実行ファイルは監視しません。
+    pub fn take(root: &Path) -> io::Result<Snapshot> {
+        let mut snapshot = Snapshot::default();
+        snapshot.scan(root)?;
+
+        Ok(snapshot)
+    }
+
+    fn scan(&mut self, dir: &Path) -> io::Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            // 走査している間に削除されたディレクトリは無視する
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    self.scan(&path)?;
+                }
+            } else if runner::is_build_output(&path) {
+                continue;
+            } else if let Ok(modified) = entry.metadata().and_then(|meta| meta.modified()) {
+                self.files.insert(path, modified);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `older` から追加・変更・削除されたファイルを列挙します。
+    pub fn changes_since(&self, older: &Snapshot) -> Vec<PathBuf> {
+        let modified = self
+            .files
+            .iter()
+            .filter(|&(path, time)| older.files.get(path) != Some(time))
+            .map(|(path, _)| path.clone());
+        let removed = older
+            .files
+            .keys()
+            .filter(|path| !self.files.contains_key(*path))
+            .cloned();
+
+        modified.chain(removed).collect()
+    }
+}
+
+/// `baseline` から何かファイルが変更されるまで待ち、変更されたファイルと、それを確かめた時点の
+/// スナップショットを返します。
+pub fn wait_for_changes(root: &Path, baseline: &Snapshot) -> io::Result<(Vec<PathBuf>, Snapshot)> {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if Snapshot::take(root)? == *baseline {
+            continue;
+        }
+
+        // エディタの保存などで続けて変更されることがあるので、落ち着くまで少し待つ
+        thread::sleep(SETTLE_TIME);
+        let snapshot = Snapshot::take(root)?;
+        let changes = snapshot.changes_since(baseline);
+        if !changes.is_empty() {
+            return Ok((changes, snapshot));
+        }
+    }
+}